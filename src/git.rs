@@ -0,0 +1,75 @@
+//! Drives git to commit the updated files.
+
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Runs `git` with `args` in `dir`, and returns its trimmed stdout.
+fn git(dir: &Path, args: &[&str]) -> Result<String, String> {
+    let output = Command::new("git")
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .map_err(|err| format!("could not run git: {}", err))?;
+    if !output.status.success() {
+        return Err(format!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Makes sure `dir` is in a git work tree.
+pub fn ensure_repository(dir: &Path) -> Result<(), String> {
+    git(dir, &["rev-parse", "--is-inside-work-tree"])
+        .map(|_| ())
+        .map_err(|_| format!("{} is not in a git repository", dir.display()))
+}
+
+/// Returns the root of the git work tree holding `dir`.
+pub fn toplevel(dir: &Path) -> Result<PathBuf, String> {
+    git(dir, &["rev-parse", "--show-toplevel"]).map(PathBuf::from)
+}
+
+/// Does the file at `path` have uncommitted changes (or is it untracked)?
+pub fn is_dirty(path: &Path) -> Result<bool, String> {
+    let dir = path.parent().unwrap_or_else(|| Path::new("."));
+    let file = path
+        .file_name()
+        .and_then(|file| file.to_str())
+        .unwrap_or("");
+    git(dir, &["status", "--porcelain", "--", file]).map(|status| !status.is_empty())
+}
+
+/// Switches to `branch`, creating it from the current commit if it doesn't exist.
+pub fn switch_branch(dir: &Path, branch: &str) -> Result<(), String> {
+    let reference = format!("refs/heads/{}", branch);
+    if git(dir, &["rev-parse", "--verify", "--quiet", &reference]).is_ok() {
+        git(dir, &["checkout", "--quiet", branch])?;
+    } else {
+        git(dir, &["checkout", "--quiet", "-b", branch])?;
+    }
+    Ok(())
+}
+
+/// Commits `paths` (and only them) with `message`, and returns the hash of the new commit.
+pub fn commit(dir: &Path, paths: &[PathBuf], message: &str) -> Result<String, String> {
+    let paths: Vec<_> = paths.iter().filter_map(|path| path.to_str()).collect();
+    let mut args = vec!["add", "--"];
+    args.extend(&paths);
+    git(dir, &args)?;
+    let mut args = vec!["commit", "--quiet", "-m", message, "--"];
+    args.extend(&paths);
+    git(dir, &args)?;
+    git(dir, &["rev-parse", "HEAD"])
+}
+
+/// Pushes `branch` to `origin`, setting it as upstream.
+pub fn push(dir: &Path, branch: &str) -> Result<(), String> {
+    git(
+        dir,
+        &["push", "--quiet", "--set-upstream", "origin", branch],
+    )
+    .map(|_| ())
+}
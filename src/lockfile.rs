@@ -0,0 +1,324 @@
+//! Reads `Cargo.lock` files.
+
+/// A package resolved in a `Cargo.lock`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LockedPackage {
+    pub name: String,
+    pub version: String,
+    /// where the package comes from (like `registry+https://...`), `None` for path packages
+    pub source: Option<String>,
+    /// the dependencies, as `name` or `name version` (when the name is ambiguous)
+    pub dependencies: Vec<String>,
+}
+
+impl LockedPackage {
+    /// Does this package depend on `version` of `name`?
+    pub fn depends_on(&self, name: &str, version: &str) -> bool {
+        self.dependencies.iter().any(|dependency| {
+            let mut parts = dependency.split_whitespace();
+            parts.next() == Some(name) && parts.next().is_none_or(|v| v == version)
+        })
+    }
+
+    /// The fully qualified package id specification of the package, as printed by `cargo pkgid`.
+    pub fn spec(&self) -> String {
+        match &self.source {
+            Some(source) => format!("{}#{}@{}", source_url(source), self.name, self.version),
+            None => format!("{}@{}", self.name, self.version),
+        }
+    }
+}
+
+/// Returns the packages of a `Cargo.lock`, in order of appearance.
+pub fn packages(content: &str) -> Vec<LockedPackage> {
+    let mut packages = vec![];
+    let mut current: Option<LockedPackage> = None;
+    let mut in_dependencies = false;
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.starts_with('[') && !in_dependencies {
+            packages.extend(current.take());
+            if line == "[[package]]" {
+                current = Some(LockedPackage::default());
+            }
+        } else if let Some(package) = current.as_mut() {
+            if in_dependencies {
+                // `dependencies = [` is followed by one quoted dependency per line, until `]`
+                let dependency = line.trim_end_matches(',').trim_matches('"');
+                if line == "]" {
+                    in_dependencies = false;
+                } else if !dependency.is_empty() {
+                    package.dependencies.push(dependency.to_string());
+                }
+            } else if let Some(value) = string_field(line, "name") {
+                package.name = value;
+            } else if let Some(value) = string_field(line, "version") {
+                package.version = value;
+            } else if let Some(value) = string_field(line, "source") {
+                package.source = Some(value);
+            } else if line.starts_with("dependencies") && line.ends_with('[') {
+                in_dependencies = true;
+            }
+        }
+    }
+    packages.extend(current);
+
+    packages
+}
+
+/// Returns the URL of a `source` (kind included), without the git query and commit.
+fn source_url(source: &str) -> &str {
+    source.split(['?', '#']).next().unwrap_or(source)
+}
+
+/// Returns the package matching a package id specification, like cargo does:
+/// `name`, `name@version`, `url#name@version` or `url#version` (the name being the last segment
+/// of the URL). Partial versions (like `1.0`) match any version they prefix, and the kind of the
+/// URL (like `registry+`) can be left out.
+pub fn resolve_spec<'a>(
+    packages: &'a [LockedPackage],
+    spec: &str,
+) -> Result<&'a LockedPackage, String> {
+    let (url, rest) = match spec.split_once('#') {
+        Some((url, rest)) => (Some(url), rest),
+        None if spec.contains("://") => (Some(spec), ""),
+        None => (None, spec),
+    };
+    let (name, version) = match rest.split_once(['@', ':']) {
+        Some((name, version)) => (name, Some(version)),
+        None if rest.starts_with(|c: char| c.is_ascii_digit()) => ("", Some(rest)),
+        None => (rest, None),
+    };
+    let name = match (name, url) {
+        ("", Some(url)) => url.trim_end_matches('/').rsplit('/').next().unwrap_or(""),
+        (name, _) => name,
+    };
+    let url = url.map(|url| url.trim_end_matches('/'));
+
+    let matching: Vec<_> = packages
+        .iter()
+        .filter(|package| package.name == name)
+        .filter(|package| {
+            version.is_none_or(|version| {
+                package.version == version || package.version.starts_with(&format!("{}.", version))
+            })
+        })
+        .filter(|package| match (url, &package.source) {
+            (None, _) => true,
+            (Some(url), Some(source)) => {
+                let source = source_url(source).trim_end_matches('/');
+                source == url
+                    || source
+                        .split_once('+')
+                        .is_some_and(|(_, source)| source == url)
+            }
+            (Some(url), None) => url.starts_with("path+"),
+        })
+        .collect();
+    match matching.as_slice() {
+        [] => Err(format!("no package in the Cargo.lock matches {}", spec)),
+        [package] => Ok(package),
+        _ => {
+            let specs: Vec<_> = matching.iter().map(|package| package.spec()).collect();
+            Err(format!(
+                "{} is ambiguous, it matches: {}",
+                spec,
+                specs.join(", ")
+            ))
+        }
+    }
+}
+
+/// A package resolved to several versions.
+#[derive(Debug, serde::Serialize)]
+pub struct Duplicate {
+    pub name: String,
+    pub versions: Vec<DuplicateVersion>,
+}
+
+#[derive(Debug, serde::Serialize)]
+pub struct DuplicateVersion {
+    pub version: String,
+    /// the packages depending on this version, as `name version`
+    pub dependents: Vec<String>,
+}
+
+/// Returns the packages resolved to more than one version, sorted by name.
+pub fn duplicates(packages: &[LockedPackage]) -> Vec<Duplicate> {
+    let mut names: Vec<&str> = packages.iter().map(|p| p.name.as_str()).collect();
+    names.sort_unstable();
+    names.dedup();
+
+    names
+        .into_iter()
+        .filter_map(|name| {
+            let versions: Vec<_> = packages
+                .iter()
+                .filter(|package| package.name == name)
+                .map(|package| DuplicateVersion {
+                    version: package.version.clone(),
+                    dependents: packages
+                        .iter()
+                        .filter(|dependent| dependent.depends_on(name, &package.version))
+                        .map(|dependent| format!("{} {}", dependent.name, dependent.version))
+                        .collect(),
+                })
+                .collect();
+            if versions.len() < 2 {
+                return None;
+            }
+            Some(Duplicate {
+                name: name.to_string(),
+                versions,
+            })
+        })
+        .collect()
+}
+
+/// Returns the value of `key = "value"` if `line` is such a statement.
+fn string_field(line: &str, key: &str) -> Option<String> {
+    let value = line
+        .strip_prefix(key)?
+        .trim_start()
+        .strip_prefix('=')?
+        .trim();
+    let value = value.strip_prefix('"')?.strip_suffix('"')?;
+    Some(value.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packages() {
+        let content = r#"# This file is automatically @generated by Cargo.
+# It is not intended for manual editing.
+version = 3
+
+[[package]]
+name = "regex"
+version = "1.4.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+dependencies = [
+ "aho-corasick",
+]
+
+[[package]]
+name = "aho-corasick"
+version = "0.7.15"
+
+[metadata]
+"checksum foo" = "bar"
+"#;
+        let packages: Vec<_> = packages(content)
+            .into_iter()
+            .map(|p| (p.name, p.version, p.dependencies))
+            .collect();
+        assert_eq!(
+            packages,
+            vec![
+                (
+                    "regex".to_string(),
+                    "1.4.3".to_string(),
+                    vec!["aho-corasick".to_string()]
+                ),
+                ("aho-corasick".to_string(), "0.7.15".to_string(), vec![]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_resolve_spec() {
+        let content = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+
+[[package]]
+name = "rand"
+version = "0.7.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "rand"
+version = "0.8.3"
+source = "registry+https://github.com/rust-lang/crates.io-index"
+
+[[package]]
+name = "rand"
+version = "0.8.3"
+source = "registry+https://my-registry.example.com/index"
+
+[[package]]
+name = "regex"
+version = "1.4.3"
+source = "git+https://github.com/rust-lang/regex?tag=1.4.3#0123abcd"
+"#;
+        let packages = packages(content);
+        let resolve = |spec| resolve_spec(&packages, spec).map(LockedPackage::spec);
+
+        assert_eq!(resolve("app").unwrap(), "app@0.1.0");
+        assert_eq!(
+            resolve("rand@0.7").unwrap(),
+            "registry+https://github.com/rust-lang/crates.io-index#rand@0.7.3"
+        );
+        assert_eq!(
+            resolve("https://my-registry.example.com/index#rand@0.8.3").unwrap(),
+            "registry+https://my-registry.example.com/index#rand@0.8.3"
+        );
+        assert_eq!(
+            resolve("registry+https://github.com/rust-lang/crates.io-index#rand:0.8.3").unwrap(),
+            "registry+https://github.com/rust-lang/crates.io-index#rand@0.8.3"
+        );
+        assert_eq!(
+            resolve("https://github.com/rust-lang/regex#1.4.3").unwrap(),
+            "git+https://github.com/rust-lang/regex#regex@1.4.3"
+        );
+
+        // the same name and version in two registries can't be told apart without the URL
+        let err = resolve("rand@0.8.3").unwrap_err();
+        assert!(err.contains("ambiguous"));
+        assert!(err.contains("my-registry.example.com"));
+        assert!(resolve("rand@0.9").unwrap_err().contains("no package"));
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let content = r#"
+[[package]]
+name = "app"
+version = "0.1.0"
+dependencies = [
+ "old",
+ "rand 0.7.3",
+ "rand 0.8.3",
+]
+
+[[package]]
+name = "old"
+version = "0.1.0"
+dependencies = [
+ "rand 0.7.3",
+]
+
+[[package]]
+name = "rand"
+version = "0.7.3"
+
+[[package]]
+name = "rand"
+version = "0.8.3"
+"#;
+        let duplicates = duplicates(&packages(content));
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!(duplicates[0].name, "rand");
+        assert_eq!(duplicates[0].versions[0].version, "0.7.3");
+        assert_eq!(
+            duplicates[0].versions[0].dependents,
+            vec!["app 0.1.0", "old 0.1.0"]
+        );
+        assert_eq!(duplicates[0].versions[1].dependents, vec!["app 0.1.0"]);
+    }
+}
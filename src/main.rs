@@ -1,19 +1,463 @@
-use clap::{App, Arg};
-use regex::Regex;
-use std::fs::File;
-use std::io::{prelude::*, BufReader};
-use std::path::{Path, PathBuf};
+mod git;
+mod lockfile;
+mod manifest;
+#[cfg(feature = "network")]
+mod registry;
+#[cfg(feature = "watch")]
+mod watch;
+
+use clap::{App, Arg, ArgMatches};
+#[cfg(feature = "watch")]
+use clap::{AppSettings, SubCommand};
+use fs2::FileExt;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
 use std::process::Command;
+use std::sync::Mutex;
 
 fn main() {
-    let matches = App::new("cargo-update-dep")
+    let matches = cli().get_matches();
+
+    // the `watch` subcommand takes the arguments of an update
+    #[cfg(feature = "watch")]
+    let (matches, watching) = match matches.subcommand_matches("watch") {
+        Some(watch) => (watch_matches(watch), true),
+        None => (matches, false),
+    };
+    #[cfg(not(feature = "watch"))]
+    let watching = false;
+    #[cfg(feature = "watch")]
+    if watching {
+        if let Err(err) = ensure_watch_supported(&matches) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if let Err(err) = ensure_format_supported(&matches) {
+        eprintln!("error: {}", err);
+        std::process::exit(1);
+    }
+
+    // cargo metadata is resolved once, for the lock and the defaults of the workspace
+    let root_dir = get_root_dir(&matches);
+    let metadata = get_metadata(&root_dir).ok();
+
+    // only one run at a time can modify the workspace, the lock is released when the process exits.
+    // Discovered manifests don't belong to a workspace, and --recursive locks each workspace itself.
+    let unlocked = [
+        "report_duplicates",
+        "print_config",
+        "check",
+        "diff_scope",
+        "emit",
+        "discover_glob",
+        "recursive",
+    ]
+    .iter()
+    .any(|mode| matches.is_present(mode));
+    let _workspace_lock = if unlocked {
+        None
+    } else {
+        match lock_workspace(&root_dir, metadata.as_ref(), !matches.is_present("no_wait")) {
+            Ok(lock) => Some(lock),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        }
+    };
+
+    let mut options = Options::from_matches(&matches, metadata.as_ref());
+
+    // a package id specification stands for both the name and the current version
+    let resolved = matches.value_of("package_id").map(|spec| {
+        let (package, requirement) =
+            resolve_package_id(&root_dir, spec, &options).unwrap_or_else(|err| {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            });
+        // packages of the same name from other sources are left alone
+        options.source = Some(DeclaredSource::of(&root_dir, &package));
+        options.package_id = Some(package.spec());
+        (package.name, requirement)
+    });
+
+    if matches.is_present("print_config") {
+        let config = Config {
+            root_dir: &root_dir,
+            mode: if watching {
+                "watch"
+            } else {
+                get_mode(&matches)
+            },
+            package: match &resolved {
+                Some((package, _)) => Some(package),
+                None => matches.value_of("dependency_name"),
+            },
+            version: match &resolved {
+                Some((_, version)) => Some(version),
+                None => matches.value_of("version"),
+            },
+            new_version: matches.value_of("new_version"),
+            set: matches.values_of("set").into_iter().flatten().collect(),
+            options: &options,
+        };
+        let json =
+            serde_json::to_string(&config).expect("Failed to serialize configuration to string");
+        println!("{}", json);
+        return;
+    }
+
+    if matches.is_present("report_duplicates") {
+        let output = match report_duplicates(&root_dir) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        let json =
+            serde_json::to_string(&output).expect("Failed to serialize duplicates to string");
+        println!("{}", json);
+        return;
+    }
+
+    if let Some(specs) = matches.values_of("set") {
+        let specs: Vec<_> = specs
+            .map(|spec| parse_spec(spec).expect("Failed to parse --set"))
+            .collect();
+        let output = match run_batch(&root_dir, &specs, &options) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        let json =
+            serde_json::to_string(&output).expect("Failed to serialize updated files to string");
+        println!("{}", json);
+        return;
+    }
+
+    #[cfg(feature = "network")]
+    if matches.is_present("update_all_compatible") {
+        let output = match update_all_compatible(
+            &root_dir,
+            matches.value_of("dependency_name"),
+            registry::CRATES_IO_INDEX,
+            &options,
+        ) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        let json =
+            serde_json::to_string(&output).expect("Failed to serialize updated files to string");
+        println!("{}", json);
+        return;
+    }
+
+    #[cfg(feature = "network")]
+    if matches.is_present("all_direct_deps") {
+        let output = match update_direct_dependencies(
+            &root_dir,
+            matches.value_of("dependency_name"),
+            registry::CRATES_IO_INDEX,
+            &options,
+        ) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        let json =
+            serde_json::to_string(&output).expect("Failed to serialize updated files to string");
+        println!("{}", json);
+        return;
+    }
+
+    if let Some(new_ref) = matches.value_of("git_ref") {
+        let package = matches
+            .value_of("dependency_name")
+            .expect("Failed to obtain dependency name");
+        let output = match update_git_refs(&root_dir, package, new_ref, &options) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        let json =
+            serde_json::to_string(&output).expect("Failed to serialize updated files to string");
+        println!("{}", json);
+        return;
+    }
+
+    if matches.is_present("pin_to_lock") {
+        let package = matches
+            .value_of("dependency_name")
+            .expect("Failed to obtain dependency name");
+        let output = match pin_to_lock(&root_dir, package, matches.value_of("version"), &options) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        let json =
+            serde_json::to_string(&output).expect("Failed to serialize updated files to string");
+        println!("{}", json);
+        return;
+    }
+
+    // extract arguments
+    let version = match &resolved {
+        Some((_, version)) => version.as_str(),
+        None => matches
+            .value_of("version")
+            .expect("Failed to obtain version"),
+    };
+
+    let new_version = matches
+        .value_of("new_version")
+        .expect("Failed to obtain new version");
+
+    let package = match &resolved {
+        Some((package, _)) => package.as_str(),
+        None => matches
+            .value_of("dependency_name")
+            .expect("Failed to obtain dependency name"),
+    };
+
+    if is_glob(package) {
+        if let Err(err) = ensure_glob_supported(&matches) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    if matches.is_present("check") {
+        let output = match check(&root_dir, package, version, new_version, &options) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        if matches.is_present("github_annotations") {
+            // GitHub expects paths relative to the repository, not to the workspace
+            let repository = git::toplevel(&root_dir).unwrap_or_else(|_| root_dir.clone());
+            // the annotations go to stderr, stdout is for the JSON report
+            for occurrence in &output.occurrences {
+                let annotation =
+                    github_annotation(&repository, occurrence, package, version, new_version);
+                eprintln!("{}", annotation);
+            }
+        }
+        let json = if matches.value_of("format") == Some("sarif") {
+            let report = sarif_report(
+                &root_dir,
+                &output.occurrences,
+                package,
+                version,
+                new_version,
+            );
+            serde_json::to_string(&report)
+        } else {
+            serde_json::to_string(&output)
+        }
+        .expect("Failed to serialize check result to string");
+        println!("{}", json);
+        if !output.up_to_date {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(scope) = matches.value_of("diff_scope") {
+        let context = matches.value_of("context").map_or(0, |context| {
+            context.parse().expect("Failed to parse --context")
+        });
+        match diff(
+            &root_dir,
+            package,
+            version,
+            new_version,
+            &options,
+            scope,
+            context,
+        ) {
+            Ok(diffs) => print!(
+                "{}",
+                render_diff(&diffs, use_color(matches.value_of("color")))
+            ),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if matches.value_of("emit") == Some("patch") {
+        match patch(&root_dir, package, version, new_version, &options) {
+            Ok(patch) => print!("{}", patch),
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if matches.is_present("recursive") {
+        let json_stream = matches.is_present("json_stream");
+        let mut workspaces = vec![];
+        run_recursive(
+            &root_dir,
+            package,
+            version,
+            new_version,
+            &options,
+            |output| {
+                if json_stream {
+                    // one line per workspace, as soon as it's done
+                    let json = serde_json::to_string(&output)
+                        .expect("Failed to serialize workspace result to string");
+                    println!("{}", json);
+                } else {
+                    workspaces.push(output);
+                }
+            },
+        );
+        if !json_stream {
+            let json = serde_json::to_string(&RecursiveOutput { workspaces })
+                .expect("Failed to serialize updated files to string");
+            println!("{}", json);
+        }
+        return;
+    }
+
+    if is_glob(package) {
+        let output = match run_glob(&root_dir, package, version, new_version, &options) {
+            Ok(output) => output,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        if matches.is_present("count") {
+            println!("{}", output.output.updated_manifests.len());
+            return;
+        }
+        let json =
+            serde_json::to_string(&output).expect("Failed to serialize updated files to string");
+        println!("{}", json);
+        return;
+    }
+
+    // without any manifest requiring the current version, fall back to the declared ones
+    if matches.is_present("relaxed_match") {
+        let versions = match declared_versions(&root_dir, package, &options) {
+            Ok(versions) => versions,
+            Err(err) => {
+                eprintln!("error: {}", err);
+                std::process::exit(1);
+            }
+        };
+        if !versions.is_empty() && !versions.iter().any(|declared| declared == version) {
+            let question = format!(
+                "No manifest requires {} {}, this will update {} {} instead.",
+                package,
+                version,
+                package,
+                versions.join(", ")
+            );
+            if !matches.is_present("yes") {
+                if let Err(err) = confirm(&question) {
+                    eprintln!("error: {}", err);
+                    std::process::exit(1);
+                }
+            }
+            options.any_version = true;
+        }
+    }
+
+    if options.commit && !matches.is_present("yes") {
+        if let Err(err) = confirm_git(&options) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    }
+
+    #[cfg(feature = "watch")]
+    if watching {
+        if let Err(err) = run_watch(&root_dir, package, version, new_version, &options) {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let output = match run(&root_dir, package, version, new_version, &options) {
+        Ok(output) => output,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            std::process::exit(1);
+        }
+    };
+    if let Some(pr_body_path) = matches.value_of("pr_body") {
+        let template = match matches.value_of("pr_body_template") {
+            Some(path) => fs::read_to_string(path).unwrap_or_else(|err| {
+                eprintln!("error: could not read {}: {}", path, err);
+                std::process::exit(1);
+            }),
+            None => PR_BODY_TEMPLATE.to_string(),
+        };
+        let body = render_pr_body(&template, &root_dir, &output, package, version, new_version);
+        if let Err(err) = fs::write(pr_body_path, body) {
+            eprintln!("error: could not write {}: {}", pr_body_path, err);
+            std::process::exit(1);
+        }
+    }
+
+    if matches.is_present("count") {
+        println!("{}", output.updated_manifests.len());
+        return;
+    }
+    let output = if matches.value_of("format") == Some("renovate") {
+        let output = RenovateOutput::new(&root_dir, &output, package, new_version);
+        serde_json::to_string(&output)
+    } else {
+        serde_json::to_string(&output)
+    }
+    .expect("Failed to serialize updated files to string");
+    println!("{}", output);
+}
+
+fn cli() -> App<'static, 'static> {
+    let app = App::new("cargo-update-dep")
         .version("1.0")
         .author("David W. <davidwg@fb.com>")
         .about("update a Rust dependency easily")
         .arg(
             Arg::with_name("version")
                 .help("the current version")
-                .required(true)
+                .required_unless_one(&[
+                    "report_duplicates",
+                    "set",
+                    "pin_to_lock",
+                    "update_all_compatible",
+                    "all_direct_deps",
+                    "git_ref",
+                    "package_id",
+                ])
                 .short("v")
                 .long("version")
                 .takes_value(true)
@@ -22,7 +466,14 @@ fn main() {
         .arg(
             Arg::with_name("new_version")
                 .help("the wished version")
-                .required(true)
+                .required_unless_one(&[
+                    "report_duplicates",
+                    "set",
+                    "pin_to_lock",
+                    "update_all_compatible",
+                    "all_direct_deps",
+                    "git_ref",
+                ])
                 .short("n")
                 .long("new-version")
                 .takes_value(true)
@@ -30,13 +481,60 @@ fn main() {
         )
         .arg(
             Arg::with_name("dependency_name")
-                .help("the name of the dependency")
-                .required(true)
+                .help("the name of the dependency (can be a glob pattern, like serde*)")
+                .required_unless_one(&[
+                    "report_duplicates",
+                    "set",
+                    "update_all_compatible",
+                    "all_direct_deps",
+                    "package_id",
+                ])
                 .short("p")
                 .long("dependency-name")
                 .takes_value(true)
                 .value_name("PACKAGE"),
         )
+        .arg(
+            Arg::with_name("package_id")
+                .help("the package id specification of the dependency (like serde@1.0.122 or registry+https://...#serde@1.0.122), resolved in the Cargo.lock, instead of --dependency-name and --version")
+                .long("package-id")
+                .takes_value(true)
+                .conflicts_with_all(&["dependency_name", "version", "set", "update_all_compatible", "all_direct_deps", "recursive"])
+                .value_name("SPEC"),
+        )
+        .arg(
+            Arg::with_name("skip_workspace_crates")
+                .help("when the dependency name is a glob pattern, don't update the crates of the workspace")
+                .long("skip-workspace-crates")
+                .requires("dependency_name"),
+        )
+        .arg(
+            Arg::with_name("set")
+                .help("bring the dependency NAME to VERSION, whatever its current version is (can be repeated)")
+                .long("set")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["version", "new_version", "dependency_name", "check", "diff_scope", "emit"])
+                .validator(|spec| parse_spec(&spec).map(|_| ()))
+                .value_name("NAME=VERSION"),
+        )
+        .arg(
+            Arg::with_name("pin_to_lock")
+                .help("set the requirements to the version resolved in the Cargo.lock (pass --version if several versions are resolved)")
+                .long("pin-to-lock")
+                .requires("dependency_name")
+                .conflicts_with_all(&["new_version", "check", "diff_scope", "emit", "recursive"]),
+        )
+        .arg(
+            Arg::with_name("git_ref")
+                .help("set the tag, rev or branch of the git dependency to this reference, instead of its version")
+                .long("git-ref")
+                .takes_value(true)
+                .requires("dependency_name")
+                .conflicts_with_all(&["version", "new_version", "set", "pin_to_lock", "update_all_compatible", "check", "diff_scope", "emit", "recursive"])
+                .value_name("REF"),
+        )
         .arg(
             Arg::with_name("manifest_path")
                 .help("path of the main Cargo.toml to analyze (can be a workspace file)")
@@ -45,182 +543,5445 @@ fn main() {
                 .takes_value(true)
                 .value_name("MANIFEST_PATH"),
         )
-        .arg(Arg::with_name("catch-cargo-cli-bug"))
-        .get_matches();
+        .arg(
+            Arg::with_name("dep_kind")
+                .help("only update members where the dependency is of this kind (can be repeated)")
+                .long("dep-kind")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .possible_values(&["normal", "dev", "build"])
+                .value_name("KIND"),
+        )
+        .arg(
+            Arg::with_name("exclude_path")
+                .help("don't update the manifests matching this gitignore-style pattern, in addition to the ones of .cargoupdateignore (can be repeated)")
+                .long("exclude-path")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("PATTERN"),
+        )
+        .arg(
+            Arg::with_name("default_members_only")
+                .help("only update the default-members of the workspace (and its root manifest)")
+                .long("default-members-only")
+                .conflicts_with("discover_glob"),
+        )
+        .arg(
+            Arg::with_name("scope")
+                .help("update every declaration, or only the one of [workspace.dependencies] that members inherit")
+                .long("scope")
+                .takes_value(true)
+                .possible_values(&["all", "workspace"])
+                .default_value("all")
+                .value_name("SCOPE"),
+        )
+        .arg(
+            Arg::with_name("ignore_case")
+                .help("match the dependency name case-insensitively")
+                .long("ignore-case"),
+        )
+        .arg(
+            Arg::with_name("by_package_name")
+                .help("only match the dependency name against the package of the declarations, not their key (which can be an alias)")
+                .long("by-package-name"),
+        )
+        .arg(
+            Arg::with_name("preserve_version_prefix")
+                .help("keep the operator of the current requirement (like ^ or ~), or its absence, in the new one")
+                .long("preserve-version-prefix"),
+        )
+        .arg(
+            Arg::with_name("normalize_inline_tables")
+                .help("rewrite the inline tables of the updated declarations like { version = \"1\", features = [\"std\"] }, instead of keeping their formatting")
+                .long("normalize-inline-tables"),
+        )
+        .arg(
+            Arg::with_name("check")
+                .help("don't modify anything, exit with an error if some manifests are out of date")
+                .long("check"),
+        )
+        .arg(
+            Arg::with_name("github_annotations")
+                .help("with --check, also print GitHub Actions annotations for the out of date manifests")
+                .long("github-annotations")
+                .requires("check"),
+        )
+        .arg(
+            Arg::with_name("diff_scope")
+                .help("don't modify anything, print the changes to the changed lines, the dependency declarations with their table, or the whole files")
+                .long("diff-scope")
+                .takes_value(true)
+                .possible_values(&["line", "table", "file"])
+                .conflicts_with_all(&["check", "recursive"])
+                .value_name("SCOPE"),
+        )
+        .arg(
+            Arg::with_name("emit")
+                .help("don't modify anything, print the changes as a patch for git apply instead")
+                .long("emit")
+                .takes_value(true)
+                .possible_values(&["patch"])
+                .conflicts_with_all(&["check", "recursive", "diff_scope", "commit", "count", "pr_body"])
+                .value_name("FORMAT"),
+        )
+        .arg(
+            Arg::with_name("context")
+                .help("with --diff-scope line, the number of unchanged lines to print around each change")
+                .long("context")
+                .takes_value(true)
+                .requires("diff_scope")
+                .validator(|context| {
+                    context
+                        .parse::<usize>()
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                })
+                .value_name("LINES"),
+        )
+        .arg(
+            Arg::with_name("color")
+                .help("color the output of --diff-scope: when printing to a terminal (and NO_COLOR is not set), always, or never")
+                .long("color")
+                .takes_value(true)
+                .possible_values(&["auto", "always", "never"])
+                .default_value("auto")
+                .value_name("WHEN"),
+        )
+        .arg(
+            Arg::with_name("no_lock_check")
+                .help("don't verify that the Cargo.lock contains the current version")
+                .long("no-lock-check"),
+        )
+        .arg(
+            Arg::with_name("explain_resolution")
+                .help("report the versions of the package in the Cargo.lock before and after the update, and the one chosen for the new requirement")
+                .long("explain-resolution")
+                .conflicts_with_all(&["check", "diff_scope", "emit", "discover_glob"]),
+        )
+        .arg(
+            Arg::with_name("no_create_lock")
+                .help("don't update the Cargo.lock if it doesn't exist, instead of letting cargo create it")
+                .long("no-create-lock"),
+        )
+        .arg(
+            Arg::with_name("discover_glob")
+                .help("update the manifests matching this glob pattern instead of the members of the workspace (the Cargo.lock files are not updated)")
+                .long("discover-glob")
+                .takes_value(true)
+                .validator(|pattern| {
+                    glob::Pattern::new(&pattern)
+                        .map(|_| ())
+                        .map_err(|err| err.to_string())
+                })
+                .value_name("PATTERN"),
+        )
+        .arg(
+            Arg::with_name("transactional")
+                .help("only modify the manifests if all of them can be updated")
+                .long("transactional"),
+        )
+        .arg(
+            Arg::with_name("commit")
+                .help("commit the updated manifests and Cargo.lock with git")
+                .long("commit")
+                .conflicts_with_all(&["set", "pin_to_lock", "update_all_compatible", "all_direct_deps", "git_ref", "check", "diff_scope", "emit", "recursive"]),
+        )
+        .arg(
+            Arg::with_name("branch")
+                .help("with --commit, create or switch to this branch before updating anything")
+                .long("branch")
+                .takes_value(true)
+                .requires("commit")
+                .value_name("BRANCH"),
+        )
+        .arg(
+            Arg::with_name("push")
+                .help("with --branch, push the branch to origin")
+                .long("push")
+                .requires("branch"),
+        )
+        .arg(
+            Arg::with_name("require_clean_git")
+                .help("refuse to modify manifests with uncommitted changes in git")
+                .long("require-clean-git"),
+        )
+        .arg(
+            Arg::with_name("allow_dirty")
+                .help("modify manifests with uncommitted changes anyway, overriding --require-clean-git")
+                .long("allow-dirty"),
+        )
+        .arg(
+            Arg::with_name("yes")
+                .help("don't ask for confirmation (before running git commands, or with --relaxed-match)")
+                .long("yes")
+                .short("y"),
+        )
+        .arg(
+            Arg::with_name("journal")
+                .help("write the original content of the modified files to this JSON file (.cargo-update-dep-journal.json by default)")
+                .long("journal")
+                .takes_value(true)
+                .min_values(0)
+                .max_values(1)
+                .value_name("PATH")
+                .conflicts_with_all(&["set", "pin_to_lock", "update_all_compatible", "all_direct_deps", "git_ref", "check", "diff_scope", "emit", "recursive"]),
+        )
+        .arg(
+            Arg::with_name("relaxed_match")
+                .help("if no manifest requires the current version, update the versions found instead (after confirmation)")
+                .long("relaxed-match"),
+        )
+        .arg(
+            Arg::with_name("no_wait")
+                .help("fail right away if another run is modifying the workspace, instead of waiting for it")
+                .long("no-wait"),
+        )
+        .arg(
+            Arg::with_name("strict")
+                .help("fail if cargo metadata fails, instead of reading the members from the root manifest")
+                .long("strict"),
+        )
+        .arg(
+            Arg::with_name("report_duplicates")
+                .help("report the packages resolved to several versions in the Cargo.lock, and exit")
+                .long("report-duplicates"),
+        )
+        .arg(
+            Arg::with_name("recursive")
+                .help("update every workspace found under the manifest path directory")
+                .long("recursive")
+                .conflicts_with_all(&["check", "discover_glob"]),
+        )
+        .arg(
+            Arg::with_name("json_stream")
+                .help("with --recursive, print the result of each workspace as a JSON line as soon as it's done")
+                .long("json-stream")
+                .requires("recursive"),
+        )
+        .arg(
+            Arg::with_name("format")
+                .help("the shape of the JSON output: the default one, the conventions of Renovate, or a SARIF report (with --check)")
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["json", "renovate", "sarif"])
+                .default_value("json")
+                .value_name("FORMAT"),
+        )
+        .arg(
+            Arg::with_name("pr_body")
+                .help("also write a markdown description of the update, for a pull request, to this file")
+                .long("pr-body")
+                .takes_value(true)
+                .conflicts_with_all(&["check", "recursive", "diff_scope", "set", "pin_to_lock"])
+                .value_name("PATH"),
+        )
+        .arg(
+            Arg::with_name("pr_body_template")
+                .help("with --pr-body, the template to render instead of the default one")
+                .long("pr-body-template")
+                .takes_value(true)
+                .requires("pr_body")
+                .value_name("TEMPLATE"),
+        )
+        .arg(
+            Arg::with_name("count")
+                .help("only print the number of updated manifests, instead of the JSON output")
+                .long("count")
+                .conflicts_with_all(&["check", "recursive", "diff_scope", "set", "pin_to_lock", "git_ref", "update_all_compatible", "all_direct_deps"]),
+        )
+        .arg(
+            Arg::with_name("print_config")
+                .help("print the configuration resolved from the arguments as JSON, and exit")
+                .long("print-config"),
+        )
+        .arg(
+            Arg::with_name("index_cache")
+                .help("with --update-all-compatible or --all-direct-deps, cache the index files looked up in the registry in this directory (and use them whatever their age when offline); ignored without the network feature")
+                .long("index-cache")
+                .takes_value(true)
+                .value_name("DIR"),
+        )
+        .arg(
+            Arg::with_name("index_cache_ttl")
+                .help("how long the cached index files are used before being fetched again, in seconds (3600 by default)")
+                .long("index-cache-ttl")
+                .takes_value(true)
+                .requires("index_cache")
+                .validator(|ttl| ttl.parse::<u64>().map(|_| ()).map_err(|err| err.to_string()))
+                .value_name("SECONDS"),
+        )
+        .arg(Arg::with_name("catch-cargo-cli-bug"));
 
-    // extract arguments
-    let version = matches
-        .value_of("version")
-        .expect("Failed to obtain version");
+    #[cfg(feature = "network")]
+    let app = app
+        .arg(
+            Arg::with_name("update_all_compatible")
+                .help("bump every crates.io dependency (or the ones matching --dependency-name) to the newest version its requirement allows")
+                .long("update-all-compatible")
+                .conflicts_with_all(&["version", "new_version", "set", "pin_to_lock", "check", "diff_scope", "emit", "recursive"]),
+        )
+        .arg(
+            Arg::with_name("all_direct_deps")
+                .help("bump the direct crates.io dependencies of the members (normal ones, or the ones of --dep-kind) to the newest version their requirement allows")
+                .long("all-direct-deps")
+                .conflicts_with_all(&["version", "new_version", "set", "pin_to_lock", "update_all_compatible", "check", "diff_scope", "emit", "recursive"]),
+        )
+        .arg(
+            Arg::with_name("allow_dependency")
+                .help("with --update-all-compatible or --all-direct-deps, only look up and bump the dependencies matching this glob pattern (can be repeated)")
+                .long("allow-dependency")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .value_name("PATTERN"),
+        )
+        .arg(
+            Arg::with_name("verify_published")
+                .help("warn if the current version was never published on crates.io (skipped offline)")
+                .long("verify-published"),
+        );
 
-    let new_version = matches
-        .value_of("new_version")
-        .expect("Failed to obtain new version");
+    // the arguments of the subcommand are parsed again by `watch_matches`
+    #[cfg(feature = "watch")]
+    let app = app.setting(AppSettings::SubcommandsNegateReqs).subcommand(
+        SubCommand::with_name("watch")
+            .about("update the manifests, then again every time one of them changes")
+            .setting(AppSettings::TrailingVarArg)
+            .setting(AppSettings::AllowLeadingHyphen)
+            .arg(
+                Arg::with_name("args")
+                    .help("the arguments of the update, like -p serde -v 1.0.122 -n 1.0.123")
+                    .multiple(true)
+                    .allow_hyphen_values(true),
+            ),
+    );
+
+    app
+}
+
+/// Returns the matches of the arguments of the `watch` subcommand, which are those of an update.
+#[cfg(feature = "watch")]
+fn watch_matches(watch: &ArgMatches) -> ArgMatches<'static> {
+    let args: Vec<OsString> = watch
+        .values_of_os("args")
+        .into_iter()
+        .flatten()
+        .map(OsString::from)
+        .collect();
+    cli()
+        .bin_name("cargo-update-dep watch")
+        .get_matches_from(std::iter::once(OsString::from("cargo-update-dep watch")).chain(args))
+}
+
+/// The modes `watch` can't run again, as they don't update the manifests to the new version
+/// or don't make sense more than once.
+#[cfg(feature = "watch")]
+const NON_WATCH_ARGS: &[&str] = &[
+    "check",
+    "diff_scope",
+    "emit",
+    "recursive",
+    "commit",
+    "count",
+    "pr_body",
+    "set",
+    "git_ref",
+    "pin_to_lock",
+    "update_all_compatible",
+    "all_direct_deps",
+    "report_duplicates",
+];
+
+/// Fails if the arguments of the `watch` subcommand select a mode it can't run again.
+#[cfg(feature = "watch")]
+fn ensure_watch_supported(matches: &ArgMatches) -> Result<(), String> {
+    if let Some(arg) = NON_WATCH_ARGS.iter().find(|arg| matches.is_present(arg)) {
+        return Err(format!(
+            "--{} is not supported by watch",
+            arg.replace('_', "-")
+        ));
+    }
+    if matches.value_of("dependency_name").is_some_and(is_glob) {
+        return Err("watch doesn't support a glob pattern as dependency name".to_string());
+    }
+    Ok(())
+}
 
-    let package = matches
-        .value_of("dependency_name")
-        .expect("Failed to obtain dependency name");
+/// Returns the directory of the manifest to analyze.
+fn get_root_dir(matches: &ArgMatches) -> PathBuf {
+    resolve_root_dir(
+        matches.value_of("manifest_path"),
+        std::env::var_os("CARGO_MANIFEST_DIR"),
+    )
+}
 
-    let root_dir = matches
-        .value_of("manifest_path")
-        .map(|s| {
-            let mut path = PathBuf::from(s);
+/// Returns the directory of `manifest_path`, or else `manifest_dir` (set by cargo for build
+/// scripts and the like), or else the current directory.
+/// The directory is canonicalized like the paths reported by `cargo metadata`,
+/// so that the manifests can be reported relative to it.
+fn resolve_root_dir(manifest_path: Option<&str>, manifest_dir: Option<OsString>) -> PathBuf {
+    let root_dir = match manifest_path {
+        Some(manifest_path) => {
+            let mut path = PathBuf::from(manifest_path);
             path.pop(); // remove Cargo.toml
+            if path.as_os_str().is_empty() {
+                path.push(".");
+            }
             path
-        })
-        .unwrap_or_else(|| std::env::current_dir().expect("Failed to open current dir"));
+        }
+        None => manifest_dir
+            .map(PathBuf::from)
+            .unwrap_or_else(|| std::env::current_dir().expect("Failed to open current dir")),
+    };
+    fs::canonicalize(&root_dir).unwrap_or(root_dir)
+}
 
-    run(&root_dir, package, version, new_version);
+/// Returns `path` relative to `root_dir`, or `None` if it is outside of it.
+/// Like the paths of `cargo metadata`, `root_dir` is canonical (see `resolve_root_dir`).
+fn relative_to_root<'a>(root_dir: &Path, path: &'a Path) -> Option<&'a Path> {
+    path.strip_prefix(root_dir).ok()
 }
 
-fn run(root_dir: &Path, package: &str, version: &str, new_version: &str) {
-    // 1. fetch all Cargo.toml file via `cargo metadata | jq '.workspace_members'`
-    let manifest_files = get_manifest_files(root_dir);
+/// Returns `path` as a relative URI, with `/` separators.
+fn relative_uri(path: &Path) -> String {
+    let components: Vec<_> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy())
+        .collect();
+    components.join("/")
+}
 
-    // 2. update them, potentially + keep track of which ones were updated
-    let mut updated = vec![];
-    for manifest_file in manifest_files {
-        if update_manifest_path(Path::new(&manifest_file), package, version, new_version) {
-            updated.push(manifest_file);
+/// The modes the renovate format can't describe, as they don't update a single dependency.
+const NON_RENOVATE_ARGS: &[&str] = &[
+    "check",
+    "diff_scope",
+    "emit",
+    "count",
+    "set",
+    "git_ref",
+    "pin_to_lock",
+    "update_all_compatible",
+    "all_direct_deps",
+    "recursive",
+];
+
+/// Fails if the `--format` of `matches` doesn't apply to the selected mode.
+fn ensure_format_supported(matches: &ArgMatches) -> Result<(), String> {
+    match matches.value_of("format") {
+        Some("sarif") if !matches.is_present("check") => {
+            Err("--format sarif is only supported with --check".to_string())
         }
+        Some("renovate") => match NON_RENOVATE_ARGS.iter().find(|arg| matches.is_present(arg)) {
+            Some(arg) => Err(format!(
+                "--format renovate is not supported with --{}",
+                arg.replace('_', "-")
+            )),
+            None => Ok(()),
+        },
+        _ => Ok(()),
     }
+}
 
-    // 3. update Cargo.lock with `cargo update`
-    update_cargo_lock(root_dir, package, version);
+/// The effective configuration, as printed by `--print-config`.
+#[derive(serde::Serialize)]
+struct Config<'a> {
+    root_dir: &'a Path,
+    mode: &'a str,
+    package: Option<&'a str>,
+    version: Option<&'a str>,
+    new_version: Option<&'a str>,
+    /// the `name=version` specifications of `--set`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    set: Vec<&'a str>,
+    #[serde(flatten)]
+    options: &'a Options,
+}
 
-    // 4. print out files changed
-    let output = Output {
-        updated_manifests: updated,
-    };
-    let updated =
-        serde_json::to_string(&output).expect("Failed to serialize updated files to string");
-    println!("{}", updated);
+/// Returns the mode picked by the flags, `update` if none.
+fn get_mode(matches: &ArgMatches) -> &'static str {
+    [
+        ("report_duplicates", "report-duplicates"),
+        ("set", "set"),
+        ("update_all_compatible", "update-all-compatible"),
+        ("all_direct_deps", "all-direct-deps"),
+        ("git_ref", "git-ref"),
+        ("pin_to_lock", "pin-to-lock"),
+        ("check", "check"),
+        ("diff_scope", "diff-scope"),
+        ("emit", "emit"),
+        ("recursive", "recursive"),
+    ]
+    .iter()
+    .find(|(arg, _)| matches.is_present(arg))
+    .map_or("update", |(_, mode)| mode)
 }
 
-#[derive(serde::Serialize)]
-struct Output {
-    updated_manifests: Vec<PathBuf>,
+/// Defaults of some options, read from `[workspace.metadata.cargo-update-dep]`
+/// in the root manifest and from `CARGO_UPDATE_DEP_*` variables, which take precedence.
+/// Flags override both.
+#[derive(serde::Deserialize, Default)]
+#[serde(default, rename_all = "kebab-case", deny_unknown_fields)]
+struct Defaults {
+    dep_kinds: Option<Vec<String>>,
+    exclude_paths: Option<Vec<String>>,
+    ignore_case: Option<bool>,
+    preserve_version_prefix: Option<bool>,
+    strict: Option<bool>,
 }
 
-#[derive(serde::Deserialize)]
-struct CargoMetadata {
-    workspace_members: Vec<String>,
+impl Defaults {
+    /// Reads the defaults of the workspace described by `metadata`,
+    /// overridden by the variables of `env`.
+    fn load(metadata: Option<&CargoMetadata>, env: impl Fn(&str) -> Option<String>) -> Self {
+        let settings = metadata
+            .and_then(|metadata| metadata.metadata.as_ref())
+            .and_then(|metadata| metadata.get("cargo-update-dep").cloned());
+        let mut defaults = settings
+            .map(|settings| {
+                serde_json::from_value(settings).unwrap_or_else(|err| {
+                    eprintln!(
+                        "warning: ignoring invalid [workspace.metadata.cargo-update-dep]: {}",
+                        err
+                    );
+                    Defaults::default()
+                })
+            })
+            .unwrap_or_default();
+
+        // lists are comma-separated, booleans are `true` or `false`
+        let list = |name: &str| {
+            env(name).map(|value| {
+                value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|item| !item.is_empty())
+                    .map(String::from)
+                    .collect()
+            })
+        };
+        let flag = |name: &str| {
+            env(name).and_then(|value| match value.as_str() {
+                "true" => Some(true),
+                "false" => Some(false),
+                _ => {
+                    eprintln!(
+                        "warning: ignoring {}={}, expected true or false",
+                        name, value
+                    );
+                    None
+                }
+            })
+        };
+        defaults.dep_kinds = list("CARGO_UPDATE_DEP_DEP_KINDS").or(defaults.dep_kinds);
+        defaults.exclude_paths = list("CARGO_UPDATE_DEP_EXCLUDE_PATHS").or(defaults.exclude_paths);
+        defaults.ignore_case = flag("CARGO_UPDATE_DEP_IGNORE_CASE").or(defaults.ignore_case);
+        defaults.preserve_version_prefix =
+            flag("CARGO_UPDATE_DEP_PRESERVE_VERSION_PREFIX").or(defaults.preserve_version_prefix);
+        defaults.strict = flag("CARGO_UPDATE_DEP_STRICT").or(defaults.strict);
+        defaults
+    }
 }
 
-fn get_manifest_files(root_dir: &Path) -> Vec<PathBuf> {
-    // run `cargo metadata`
-    let output = Command::new("cargo")
-        .current_dir(root_dir)
-        .arg("metadata")
-        .output()
-        .expect("failed to execute process");
-    assert!(output.status.success());
+/// How long the cached index files are used by default, in seconds.
+const INDEX_CACHE_TTL: u64 = 3600;
 
-    // json load the result
-    let cargo_metadata: CargoMetadata = serde_json::from_slice(&output.stdout)
-        .expect("Failed to deserialize cargo metadata output");
+/// The default location of the journal, relative to the workspace.
+const JOURNAL_FILE: &str = ".cargo-update-dep-journal.json";
 
-    // return data
-    let re = Regex::new(r"file://(.*)\)").unwrap();
+/// Options tweaking which manifests get updated.
+#[derive(Default, Clone, serde::Serialize)]
+struct Options {
+    /// only update members depending on the package with one of these kinds (empty means any)
+    dep_kinds: Vec<String>,
+    /// gitignore-style patterns of manifests to leave alone, on top of `.cargoupdateignore`
+    exclude_paths: Vec<String>,
+    /// glob patterns of the dependencies to look up in the registry (empty means any)
+    allowed_dependencies: Vec<String>,
+    /// the directory caching the index files looked up in the registry
+    index_cache: Option<PathBuf>,
+    /// how long the cached index files are used before being fetched again, in seconds
+    index_cache_ttl: u64,
+    /// match dependency names case-insensitively
+    ignore_case: bool,
+    /// only match dependencies by the name of their package, not by their key
+    by_package_name: bool,
+    /// keep the operator of the current requirement (or its absence) in the new one
+    preserve_version_prefix: bool,
+    /// rewrite the inline tables of the updated declarations in a canonical form
+    normalize_inline_tables: bool,
+    /// only update the `default-members` of the workspace
+    default_members_only: bool,
+    /// only update the declarations of `[workspace.dependencies]`
+    workspace_scope: bool,
+    /// report how the Cargo.lock resolves the package after the update
+    explain_resolution: bool,
+    /// don't verify that the Cargo.lock contains the current version
+    no_lock_check: bool,
+    /// `watch` runs the update again, after the first run moved the Cargo.lock to the new version
+    #[serde(skip)]
+    rerun: bool,
+    /// don't run `cargo update` if it would create the Cargo.lock
+    no_create_lock: bool,
+    /// find manifests with this glob pattern instead of `cargo metadata`
+    discover_glob: Option<String>,
+    /// only modify the manifests if all of them can be updated
+    transactional: bool,
+    /// warn if the current version was never published on crates.io
+    verify_published: bool,
+    /// commit the updated files with git
+    commit: bool,
+    /// with `commit`, the branch to create or switch to before updating anything
+    branch: Option<String>,
+    /// with `branch`, push it to `origin`
+    push: bool,
+    /// refuse to modify manifests with uncommitted changes
+    require_clean_git: bool,
+    /// where to write the journal of the run
+    journal: Option<PathBuf>,
+    /// fail if `cargo metadata` fails, instead of finding members on a best-effort basis
+    strict: bool,
+    /// fail if another run holds the lock of a workspace, instead of waiting for it
+    no_wait: bool,
+    /// with a glob pattern as dependency name, leave alone the crates of the workspace
+    skip_workspace_crates: bool,
+    /// update the package whatever its current version is
+    any_version: bool,
+    /// with `--package-id`, only update the declarations from the source of the resolved package
+    source: Option<DeclaredSource>,
+    /// with `--package-id`, the specification of the resolved package, for `cargo update`
+    package_id: Option<String>,
+}
 
-    cargo_metadata
-        .workspace_members
-        .iter()
-        .map(|path| {
-            let caps = re.captures(path).expect("Failed to capture path");
-            let mut path = PathBuf::from(caps.get(1).unwrap().as_str());
-            path.push("Cargo.toml");
-            path
-        })
-        .collect()
+impl Options {
+    fn from_matches(matches: &ArgMatches, metadata: Option<&CargoMetadata>) -> Self {
+        let defaults = Defaults::load(metadata, |name| std::env::var(name).ok());
+        Self::from_matches_and_defaults(matches, defaults)
+    }
+
+    fn from_matches_and_defaults(matches: &ArgMatches, defaults: Defaults) -> Self {
+        Self {
+            dep_kinds: matches
+                .values_of("dep_kind")
+                .map(|kinds| kinds.map(String::from).collect())
+                .or(defaults.dep_kinds)
+                .unwrap_or_default(),
+            exclude_paths: matches
+                .values_of("exclude_path")
+                .map(|patterns| patterns.map(String::from).collect())
+                .or(defaults.exclude_paths)
+                .unwrap_or_default(),
+            allowed_dependencies: matches
+                .values_of("allow_dependency")
+                .map(|patterns| patterns.map(String::from).collect())
+                .unwrap_or_default(),
+            index_cache: matches.value_of("index_cache").map(PathBuf::from),
+            index_cache_ttl: matches
+                .value_of("index_cache_ttl")
+                .map_or(INDEX_CACHE_TTL, |ttl| {
+                    ttl.parse().expect("Failed to parse --index-cache-ttl")
+                }),
+            ignore_case: matches.is_present("ignore_case") || defaults.ignore_case == Some(true),
+            by_package_name: matches.is_present("by_package_name"),
+            preserve_version_prefix: matches.is_present("preserve_version_prefix")
+                || defaults.preserve_version_prefix == Some(true),
+            normalize_inline_tables: matches.is_present("normalize_inline_tables"),
+            default_members_only: matches.is_present("default_members_only"),
+            workspace_scope: matches.value_of("scope") == Some("workspace"),
+            explain_resolution: matches.is_present("explain_resolution"),
+            no_lock_check: matches.is_present("no_lock_check"),
+            rerun: false,
+            no_create_lock: matches.is_present("no_create_lock"),
+            discover_glob: matches.value_of("discover_glob").map(String::from),
+            transactional: matches.is_present("transactional"),
+            verify_published: matches.is_present("verify_published"),
+            commit: matches.is_present("commit"),
+            branch: matches.value_of("branch").map(String::from),
+            push: matches.is_present("push"),
+            require_clean_git: matches.is_present("require_clean_git")
+                && !matches.is_present("allow_dirty"),
+            journal: matches.is_present("journal").then(|| {
+                matches
+                    .value_of("journal")
+                    .map(PathBuf::from)
+                    .unwrap_or_else(|| get_root_dir(matches).join(JOURNAL_FILE))
+            }),
+            strict: matches.is_present("strict") || defaults.strict == Some(true),
+            no_wait: matches.is_present("no_wait"),
+            skip_workspace_crates: matches.is_present("skip_workspace_crates"),
+            any_version: false,
+            source: None,
+            package_id: None,
+        }
+    }
 }
 
-fn update_manifest_path(
-    manifest_path: &Path,
+fn run(
+    root_dir: &Path,
     package: &str,
     version: &str,
     new_version: &str,
-) -> bool {
-    // initialize regexes (not efficient, we re-initiliaze every time...)
-    let re = Regex::new(&format!(r#"^[\t\s]*{}[\t\s]*="#, package)).unwrap();
-    let re2 = Regex::new(&format!(r#"package[\t\s]*=[\t\s]*"{}""#, package)).unwrap();
-    let version = format!(r#""{}""#, version);
-    let new_version = format!(r#""{}""#, new_version);
+    options: &Options,
+) -> Result<Output, String> {
+    // discovered manifests don't belong to a single workspace, so we leave lockfiles alone
+    let update_lock = options.discover_glob.is_none();
 
-    // read manifest file line by line
-    let mut updated = false;
-    let file = File::open(manifest_path).expect("Failed to open manifest file");
-    let mut lines = vec![];
-    for line in BufReader::new(file).lines() {
-        let mut line = line.expect("Failed to read line of file");
+    // the dependency name can be the key of an alias, while cargo knows the package
+    let locked_name = if update_lock {
+        resolve_package_name(root_dir, package, options)?
+    } else {
+        package.to_string()
+    };
 
-        // found the package
-        if re.is_match(&line) || re2.is_match(&line) {
-            let line2 = line.replace(&version, &new_version);
-            if line != line2 {
-                line = line2;
-                updated = true;
+    // 0. make sure `cargo update` will find the current version
+    let mut locked_version = None;
+    if update_lock && !options.no_lock_check && !options.any_version {
+        locked_version = match check_lock(root_dir, &locked_name, version) {
+            Err(_) if options.rerun => check_lock(root_dir, &locked_name, new_version)?,
+            locked_version => locked_version?,
+        };
+    }
+    #[cfg(feature = "network")]
+    if options.verify_published {
+        verify_published(registry::CRATES_IO_INDEX, &locked_name, version);
+    }
+    if options.commit {
+        git::ensure_repository(root_dir)?;
+        if let Some(branch) = &options.branch {
+            git::switch_branch(root_dir, branch)?;
+        }
+    }
+
+    // 1-2. update the manifests
+    let mut output = update_manifests(root_dir, package, version, new_version, options)?;
+
+    // 3. update Cargo.lock with `cargo update`
+    if update_lock {
+        let lock_path = get_lock_path(root_dir);
+        let original_lock = fs::read_to_string(&lock_path).ok();
+        let pkgid = match &options.package_id {
+            // the specification may name the version the previous run replaced
+            Some(package_id) if !options.rerun => package_id.clone(),
+            _ if options.any_version => locked_name.clone(),
+            _ => {
+                let version = locked_version.as_deref().unwrap_or(version);
+                format!("{}:{}", locked_name, version)
+            }
+        };
+        output.lock_update = update_cargo_lock(root_dir, &[pkgid], options);
+        if options.explain_resolution && output.lock_update.is_some() {
+            let new_lock = fs::read_to_string(&lock_path).ok();
+            output.resolution = Some(explain_resolution(
+                &locked_name,
+                new_version,
+                original_lock.as_deref().unwrap_or_default(),
+                new_lock.as_deref().unwrap_or_default(),
+            ));
+        }
+        if let (Some(original), Some(_)) = (original_lock, &output.lock_update) {
+            output.originals.push((lock_path, original));
+        }
+    }
+
+    // report the versions that were actually found
+    if options.any_version {
+        for declaration in &output.updated_declarations {
+            if !output.relaxed_versions.contains(&declaration.version) {
+                output.relaxed_versions.push(declaration.version.clone());
+            }
+        }
+    }
+
+    // keep track of the original contents
+    if let Some(journal_path) = &options.journal {
+        let journal = Journal {
+            package: package.to_string(),
+            version: version.to_string(),
+            new_version: new_version.to_string(),
+            files: output
+                .originals
+                .iter()
+                .map(|(path, original)| JournalFile {
+                    path: path.clone(),
+                    original: original.clone(),
+                })
+                .collect(),
+        };
+        let json = serde_json::to_string_pretty(&journal).expect("Failed to serialize journal");
+        write_all_or_nothing(&[(journal_path.clone(), json)])?;
+    }
+
+    // 4. commit the changes
+    if options.commit {
+        output.git = Some(commit_changes(
+            root_dir,
+            &output,
+            package,
+            version,
+            new_version,
+            options,
+        )?);
+    }
+
+    // 5. return files changed
+    Ok(output)
+}
+
+/// Fails if some of `paths` have uncommitted changes, listing them.
+/// Files outside of a git repository can't be checked, and are only warned about.
+fn ensure_clean_git(paths: &[PathBuf]) -> Result<(), String> {
+    let mut dirty = vec![];
+    for path in paths {
+        let dir = path.parent().unwrap_or(path);
+        if git::ensure_repository(dir).is_err() {
+            eprintln!(
+                "warning: {} is not in a git repository, it can't be checked for uncommitted changes",
+                path.display()
+            );
+            continue;
+        }
+        if git::is_dirty(path)? {
+            dirty.push(path.display().to_string());
+        }
+    }
+    if !dirty.is_empty() {
+        return Err(format!(
+            "some manifests have uncommitted changes, commit them or pass --allow-dirty: {}",
+            dirty.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// Runs the update, then runs it again every time a manifest changes,
+/// printing the result of each run as a line of JSON.
+#[cfg(feature = "watch")]
+fn run_watch(
+    root_dir: &Path,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+) -> Result<(), String> {
+    let report = |result: Result<Output, String>| match result {
+        Ok(output) => {
+            let json = serde_json::to_string(&output)
+                .expect("Failed to serialize updated files to string");
+            println!("{}", json);
+        }
+        Err(err) => eprintln!("error: {}", err),
+    };
+    let mut manifest_files = get_manifest_files(root_dir, package, options)?.0;
+    if !manifest_files.contains(&root_dir.join("Cargo.toml")) {
+        manifest_files.push(root_dir.join("Cargo.toml"));
+    }
+    report(run(root_dir, package, version, new_version, options));
+    watch::watch(&manifest_files, || {
+        report(rerun(root_dir, package, version, new_version, options))
+    })
+}
+
+/// Runs the update again for `watch`, once the manifests were edited.
+#[cfg(feature = "watch")]
+fn rerun(
+    root_dir: &Path,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+) -> Result<Output, String> {
+    forget_metadata();
+    let options = Options {
+        rerun: true,
+        ..options.clone()
+    };
+    run(root_dir, package, version, new_version, &options)
+}
+
+/// Asks for confirmation before running git commands.
+fn confirm_git(options: &Options) -> Result<(), String> {
+    let mut actions = vec!["commit the changes"];
+    if options.branch.is_some() {
+        actions.insert(0, "switch branch");
+    }
+    if options.push {
+        actions.push("push the branch");
+    }
+    confirm(&format!("This will {}.", actions.join(", ")))
+}
+
+/// Asks the user to confirm `question`, which requires an interactive terminal.
+fn confirm(question: &str) -> Result<(), String> {
+    use std::io::{BufRead, IsTerminal, Write};
+
+    if !std::io::stdin().is_terminal() {
+        return Err("not running interactively, pass --yes to confirm".to_string());
+    }
+    eprint!("{} Continue? [y/N] ", question);
+    std::io::stderr().flush().expect("Failed to flush stderr");
+
+    let mut answer = String::new();
+    std::io::stdin()
+        .lock()
+        .read_line(&mut answer)
+        .map_err(|err| err.to_string())?;
+    if !matches!(answer.trim(), "y" | "Y" | "yes") {
+        return Err("aborted".to_string());
+    }
+    Ok(())
+}
+
+/// The default template of `--pr-body`.
+const PR_BODY_TEMPLATE: &str = "Bump `{package}` from {version} to {new_version}
+
+{changes}
+
+See [{package} on crates.io]({crates_io}) for the changes of this version.
+";
+
+/// Renders a pull request description of the update, replacing in `template`:
+/// `{package}`, `{version}`, `{new_version}`, `{crates_io}` (the page of the package)
+/// and `{changes}` (a markdown list of the updated requirements).
+fn render_pr_body(
+    template: &str,
+    root_dir: &Path,
+    output: &Output,
+    package: &str,
+    version: &str,
+    new_version: &str,
+) -> String {
+    let changes: Vec<_> = output
+        .updated_declarations
+        .iter()
+        .map(|declaration| {
+            let path =
+                relative_to_root(root_dir, &declaration.manifest).unwrap_or(&declaration.manifest);
+            format!(
+                "- `{}` (`{}`): {} → {}",
+                path.display(),
+                declaration.table,
+                declaration.version,
+                new_version
+            )
+        })
+        .collect();
+    template
+        .replace("{package}", package)
+        .replace("{version}", version)
+        .replace("{new_version}", new_version)
+        .replace(
+            "{crates_io}",
+            &format!("https://crates.io/crates/{}", package),
+        )
+        .replace("{changes}", &changes.join("\n"))
+}
+
+/// Commits the updated manifests and Cargo.lock, and pushes the branch if asked to.
+fn commit_changes(
+    root_dir: &Path,
+    output: &Output,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+) -> Result<GitOutput, String> {
+    let mut git_output = GitOutput {
+        branch: options.branch.clone(),
+        commit: None,
+        pushed: false,
+    };
+    if output.updated_manifests.is_empty() {
+        eprintln!("warning: no manifest was updated, nothing to commit");
+        return Ok(git_output);
+    }
+
+    let mut paths = output.updated_manifests.clone();
+    let lock_path = get_lock_path(root_dir);
+    if output.lock_update.is_some() && lock_path.exists() {
+        paths.push(lock_path);
+    }
+    let message = format!("Bump {} from {} to {}", package, version, new_version);
+    git_output.commit = Some(git::commit(root_dir, &paths, &message)?);
+
+    if let (true, Some(branch)) = (options.push, &options.branch) {
+        git::push(root_dir, branch)?;
+        git_output.pushed = true;
+    }
+    Ok(git_output)
+}
+
+/// Is this dependency name a glob pattern?
+fn is_glob(package: &str) -> bool {
+    package.contains(['*', '?', '['])
+}
+
+/// The flags of a single update that don't apply to a glob pattern as dependency name.
+const SINGLE_DEPENDENCY_ARGS: &[&str] = &[
+    "commit",
+    "journal",
+    "relaxed_match",
+    "pr_body",
+    "explain_resolution",
+    "verify_published",
+];
+
+/// Fails if `matches` holds flags that only apply to the update of a single dependency.
+fn ensure_glob_supported(matches: &ArgMatches) -> Result<(), String> {
+    let unsupported = SINGLE_DEPENDENCY_ARGS
+        .iter()
+        .find(|arg| matches.is_present(arg))
+        .map(|arg| format!("--{}", arg.replace('_', "-")))
+        .or_else(|| {
+            (matches.value_of("format") == Some("renovate"))
+                .then(|| "--format renovate".to_string())
+        });
+    match unsupported {
+        Some(arg) => Err(format!(
+            "{} is not supported with a glob pattern as dependency name",
+            arg
+        )),
+        None => Ok(()),
+    }
+}
+
+/// Updates every dependency whose name matches the glob `pattern`, running `cargo update` once.
+fn run_glob(
+    root_dir: &Path,
+    pattern: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+) -> Result<GlobOutput, String> {
+    let (names, skipped_workspace_crates) = expand_dependency_names(root_dir, pattern, options)?;
+
+    // 1-2. stage the manifests of all the packages, so they are written together,
+    // only keeping the packages that some manifests require at `version`
+    let mut packages = vec![];
+    let mut staged = vec![];
+    let mut output = Output::default();
+    for package in names {
+        let package_output = stage_manifests(
+            root_dir,
+            &package,
+            version,
+            new_version,
+            options,
+            &mut staged,
+        )?;
+        if package_output.updated_manifests.is_empty() {
+            continue;
+        }
+        packages.push(package);
+        merge_paths(
+            &mut output.updated_manifests,
+            package_output.updated_manifests,
+        );
+        merge_paths(
+            &mut output.inherited_manifests,
+            package_output.inherited_manifests,
+        );
+        merge_paths(
+            &mut output.overridden_manifests,
+            package_output.overridden_manifests,
+        );
+        merge_paths(
+            &mut output.diverging_manifests,
+            package_output.diverging_manifests,
+        );
+        output
+            .updated_aliases
+            .extend(package_output.updated_aliases);
+        merge_paths(
+            &mut output.unversioned_git_manifests,
+            package_output.unversioned_git_manifests,
+        );
+        for skipped in package_output.skipped_manifests {
+            if !output
+                .skipped_manifests
+                .iter()
+                .any(|known| known.manifest == skipped.manifest)
+            {
+                output.skipped_manifests.push(skipped);
+            }
+        }
+    }
+
+    // 0. make sure `cargo update` will find the current versions
+    let update_lock = options.discover_glob.is_none();
+    let mut locked_versions = vec![None; packages.len()];
+    if update_lock && !options.no_lock_check {
+        for (package, locked_version) in packages.iter().zip(&mut locked_versions) {
+            *locked_version = check_lock(root_dir, package, version)?;
+        }
+    }
+    write_manifests(&staged, options)?;
+
+    // 3. update Cargo.lock with a single `cargo update`
+    if update_lock && !packages.is_empty() {
+        let pkgids: Vec<_> = packages
+            .iter()
+            .zip(&locked_versions)
+            .map(|(package, locked_version)| {
+                format!(
+                    "{}:{}",
+                    package,
+                    locked_version.as_deref().unwrap_or(version)
+                )
+            })
+            .collect();
+        output.lock_update = update_cargo_lock(root_dir, &pkgids, options);
+    }
+
+    Ok(GlobOutput {
+        packages,
+        skipped_workspace_crates,
+        output,
+    })
+}
+
+/// Appends the paths that are not already in `paths`.
+fn merge_paths(paths: &mut Vec<PathBuf>, new_paths: Vec<PathBuf>) {
+    for path in new_paths {
+        if !paths.contains(&path) {
+            paths.push(path);
+        }
+    }
+}
+
+/// Returns the names of the dependencies of the workspace members matching the glob `pattern`,
+/// sorted, and the names of the workspace crates that were left out.
+fn expand_dependency_names(
+    root_dir: &Path,
+    pattern: &str,
+    options: &Options,
+) -> Result<(Vec<String>, Vec<String>), String> {
+    let pattern = glob::Pattern::new(pattern)
+        .map_err(|err| format!("invalid dependency name pattern {}: {}", pattern, err))?;
+    let metadata = get_metadata(root_dir)?;
+    let members = get_workspace_members(metadata);
+    let member_names: Vec<_> = members.iter().map(|member| member.name.clone()).collect();
+
+    let mut names: Vec<_> = members
+        .into_iter()
+        .flat_map(|member| member.dependencies)
+        .map(|dependency| dependency.name)
+        .filter(|name| pattern.matches(name))
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+
+    let mut skipped = vec![];
+    if options.skip_workspace_crates {
+        names.retain(|name| {
+            let internal = member_names.contains(name);
+            if internal {
+                skipped.push(name.clone());
+            }
+            !internal
+        });
+    }
+    Ok((names, skipped))
+}
+
+/// Warns if `version` of `package` was never published on the registry.
+/// Returns whether the version could be verified as published.
+#[cfg(feature = "network")]
+fn verify_published(index_url: &str, package: &str, version: &str) -> bool {
+    if registry::is_offline() {
+        eprintln!(
+            "warning: offline, skipping the verification of {} {}",
+            package, version
+        );
+        return false;
+    }
+    let content = match registry::fetch(index_url, package) {
+        Ok(content) => content,
+        Err(err) => {
+            eprintln!(
+                "warning: {}, skipping the verification of {} {}",
+                err, package, version
+            );
+            return false;
+        }
+    };
+    let published = registry::is_published(&registry::versions(&content), version);
+    if !published {
+        eprintln!(
+            "warning: {} {} is not a published version, it might be a typo",
+            package, version
+        );
+    }
+    published
+}
+
+/// Takes the advisory lock of the workspace described by `metadata` (if `cargo metadata` worked),
+/// waiting for other runs to release it if `wait` is set.
+/// The lock is held until the returned file is closed. The file lives in the target directory,
+/// so that it stays out of the sources, or next to the Cargo.lock if there's no target directory yet.
+fn lock_workspace(
+    root_dir: &Path,
+    metadata: Option<&CargoMetadata>,
+    wait: bool,
+) -> Result<fs::File, String> {
+    let lock_path = match metadata {
+        Some(metadata) if metadata.target_directory.is_dir() => {
+            metadata.target_directory.join("cargo-update-dep.lock")
+        }
+        Some(metadata) => metadata.workspace_root.join(".cargo-update-dep.lock"),
+        None => root_dir.join(".cargo-update-dep.lock"),
+    };
+    let file = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&lock_path)
+        .map_err(|err| format!("could not open {}: {}", lock_path.display(), err))?;
+
+    if file.try_lock_exclusive().is_err() {
+        if !wait {
+            return Err(format!(
+                "another run holds {}, not waiting because of --no-wait",
+                lock_path.display()
+            ));
+        }
+        eprintln!("waiting for another run to release {}", lock_path.display());
+        file.lock_exclusive()
+            .map_err(|err| format!("could not lock {}: {}", lock_path.display(), err))?;
+    }
+    Ok(file)
+}
+
+/// Returns the path of the Cargo.lock of the workspace.
+fn get_lock_path(root_dir: &Path) -> PathBuf {
+    get_metadata(root_dir)
+        .map(|metadata| metadata.workspace_root)
+        .unwrap_or_else(|_| root_dir.to_path_buf())
+        .join("Cargo.lock")
+}
+
+/// Output of `--report-duplicates`.
+#[derive(serde::Serialize)]
+struct DuplicatesOutput {
+    duplicates: Vec<lockfile::Duplicate>,
+}
+
+/// Reports the packages resolved to several versions in the Cargo.lock of the workspace.
+fn report_duplicates(root_dir: &Path) -> Result<DuplicatesOutput, String> {
+    let lock_path = get_lock_path(root_dir);
+    let content = fs::read_to_string(&lock_path)
+        .map_err(|err| format!("could not read {}: {}", lock_path.display(), err))?;
+    Ok(DuplicatesOutput {
+        duplicates: lockfile::duplicates(&lockfile::packages(&content)),
+    })
+}
+
+/// Verifies that the Cargo.lock of the workspace contains a version of `package` matching
+/// the `version` requirement (`1.4` matches `1.4.0`). Returns the locked version,
+/// or `None` if there's no Cargo.lock to verify.
+fn check_lock(root_dir: &Path, package: &str, version: &str) -> Result<Option<String>, String> {
+    let lock_path = get_lock_path(root_dir);
+    let content = match fs::read_to_string(&lock_path) {
+        Ok(content) => content,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(err) => {
+            return Err(format!(
+                "could not read {} ({}), use --no-lock-check to skip this verification",
+                lock_path.display(),
+                err
+            ))
+        }
+    };
+
+    let requirement = semver::VersionReq::parse(version)
+        .map_err(|err| format!("invalid version {}: {}", version, err))?;
+
+    let locked = lockfile::packages(&content)
+        .into_iter()
+        .filter(|locked| locked.name == package)
+        .filter_map(|locked| semver::Version::parse(&locked.version).ok())
+        .filter(|locked| requirement.matches(locked))
+        .max();
+    match locked {
+        Some(locked) => Ok(Some(locked.to_string())),
+        None => Err(format!(
+            "{} does not contain {} {}, use --no-lock-check to skip this verification",
+            lock_path.display(),
+            package,
+            version
+        )),
+    }
+}
+
+/// Returns the package matching the package id specification `spec` in the Cargo.lock
+/// of the workspace, and the requirement of the manifests admitting its version.
+fn resolve_package_id(
+    root_dir: &Path,
+    spec: &str,
+    options: &Options,
+) -> Result<(lockfile::LockedPackage, String), String> {
+    let lock_path = get_lock_path(root_dir);
+    let content = fs::read_to_string(&lock_path).map_err(|err| {
+        format!(
+            "could not read {} to resolve --package-id: {}",
+            lock_path.display(),
+            err
+        )
+    })?;
+    let packages = lockfile::packages(&content);
+    let package = lockfile::resolve_spec(&packages, spec)?.clone();
+    let locked = semver::Version::parse(&package.version)
+        .map_err(|err| format!("invalid version {}: {}", package.version, err))?;
+    let source = DeclaredSource::of(root_dir, &package);
+
+    // the manifests require a range, which the locked version is only part of
+    let mut requirements = vec![];
+    for manifest_file in get_manifest_files(root_dir, &package.name, options)?.0 {
+        let content = fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+        for dependency in manifest::dependencies(&content) {
+            if !is_package(&dependency, &package.name, options) || !source.declares(&dependency) {
+                continue;
+            }
+            let requirement = match dependency.version {
+                Some(version) => version.value,
+                None => continue,
+            };
+            let admits = semver::VersionReq::parse(&requirement)
+                .is_ok_and(|requirement| requirement.matches(&locked));
+            if admits && !requirements.contains(&requirement) {
+                requirements.push(requirement);
+            }
+        }
+    }
+
+    match requirements.as_slice() {
+        [] => Err(format!(
+            "no manifest has a requirement on {} admitting {}",
+            package.name, package.version
+        )),
+        [requirement] => Ok((package.clone(), requirement.clone())),
+        _ => Err(format!(
+            "several requirements on {} admit {} ({}), use --dependency-name and --version to pick one",
+            package.name,
+            package.version,
+            requirements.join(", ")
+        )),
+    }
+}
+
+/// The indexes of crates.io, as found in the sources of the Cargo.lock.
+const CRATES_IO_INDEXES: &[&str] = &[
+    "https://github.com/rust-lang/crates.io-index",
+    "https://index.crates.io",
+];
+
+/// How the manifests declare the source of a package, to tell apart packages of the same name.
+#[derive(Clone, Debug, PartialEq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum DeclaredSource {
+    /// crates.io or a path: neither `registry` nor `git` is set
+    Default,
+    /// a registry of the cargo configuration, under one of these names
+    Registry(Vec<String>),
+    /// a git repository, by URL
+    Git(String),
+}
+
+impl DeclaredSource {
+    /// Returns how the manifests of the workspace at `root_dir` declare `package`.
+    fn of(root_dir: &Path, package: &lockfile::LockedPackage) -> Self {
+        let source = match &package.source {
+            Some(source) => source,
+            None => return Self::Default,
+        };
+        let (kind, url) = source.split_once('+').unwrap_or(("registry", source));
+        let url = url.split(['?', '#']).next().unwrap_or(url);
+        let url = url.trim_end_matches('/');
+        match kind {
+            "git" => Self::Git(url.to_string()),
+            _ if CRATES_IO_INDEXES.contains(&url) => Self::Default,
+            _ => Self::Registry(registry_names(root_dir, source)),
+        }
+    }
+
+    /// Is `dependency` declared with this source?
+    fn declares(&self, dependency: &manifest::Dependency) -> bool {
+        match self {
+            Self::Default => dependency.registry.is_none() && dependency.git.is_none(),
+            Self::Registry(names) => dependency.registry.as_ref().is_some_and(|registry| {
+                // `CARGO_REGISTRIES_<NAME>_INDEX` variables spell names in upper snake case
+                let spelling = |name: &str| name.to_uppercase().replace('-', "_");
+                names
+                    .iter()
+                    .any(|name| spelling(name) == spelling(&registry.value))
+            }),
+            Self::Git(url) => dependency
+                .git
+                .as_ref()
+                .is_some_and(|git| git.value.trim_end_matches('/') == url),
+        }
+    }
+}
+
+/// Returns the names the cargo configuration gives to the registry of the Cargo.lock `source`:
+/// `CARGO_REGISTRIES_<NAME>_INDEX` variables, then the `.cargo/config.toml` files of `root_dir`
+/// and its parents, then the one of the cargo home.
+fn registry_names(root_dir: &Path, source: &str) -> Vec<String> {
+    let index = |url: &str| {
+        url.trim_start_matches("registry+")
+            .trim_end_matches('/')
+            .to_string()
+    };
+    let source = index(source);
+
+    let mut names = vec![];
+    for (key, value) in std::env::vars() {
+        let name = key
+            .strip_prefix("CARGO_REGISTRIES_")
+            .and_then(|key| key.strip_suffix("_INDEX"));
+        if let Some(name) = name.filter(|_| index(&value) == source) {
+            names.push(name.to_string());
+        }
+    }
+
+    let root_dir = fs::canonicalize(root_dir).unwrap_or_else(|_| root_dir.to_path_buf());
+    let cargo_home = std::env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cargo")));
+    let config_dirs = root_dir
+        .ancestors()
+        .map(|dir| dir.join(".cargo"))
+        .chain(cargo_home);
+    for config_dir in config_dirs {
+        for config_file in ["config.toml", "config"] {
+            let content = fs::read_to_string(config_dir.join(config_file)).unwrap_or_default();
+            for (path, value) in manifest::strings(&content) {
+                if let [registries, name, key] = path.as_slice() {
+                    if registries == "registries" && key == "index" && index(&value) == source {
+                        names.push(name.clone());
+                    }
+                }
+            }
+        }
+    }
+    names
+}
+
+/// Sets the requirements on `package` to the version resolved in the Cargo.lock.
+/// Without `version`, any requirement is updated, but the lockfile must resolve a single version.
+fn pin_to_lock(
+    root_dir: &Path,
+    package: &str,
+    version: Option<&str>,
+    options: &Options,
+) -> Result<Output, String> {
+    let new_version = get_locked_version(root_dir, package, version)?;
+    let options = Options {
+        any_version: version.is_none(),
+        ..options.clone()
+    };
+
+    // the Cargo.lock already resolves the new version, no need to run `cargo update`
+    update_manifests(
+        root_dir,
+        package,
+        version.unwrap_or("*"),
+        &new_version,
+        &options,
+    )
+}
+
+/// Returns the version of `package` resolved in the Cargo.lock of the workspace,
+/// among the ones matching the `version` requirement if given.
+fn get_locked_version(
+    root_dir: &Path,
+    package: &str,
+    version: Option<&str>,
+) -> Result<String, String> {
+    let requirement = version
+        .map(semver::VersionReq::parse)
+        .transpose()
+        .map_err(|err| format!("invalid version {}: {}", version.unwrap_or_default(), err))?;
+
+    let lock_path = get_lock_path(root_dir);
+    let content = fs::read_to_string(&lock_path)
+        .map_err(|err| format!("could not read {}: {}", lock_path.display(), err))?;
+    let versions: Vec<_> = lockfile::packages(&content)
+        .into_iter()
+        .filter(|locked| locked.name == package)
+        .map(|locked| locked.version)
+        .filter(|locked| {
+            requirement.as_ref().is_none_or(|requirement| {
+                semver::Version::parse(locked).is_ok_and(|locked| requirement.matches(&locked))
+            })
+        })
+        .collect();
+
+    match versions.as_slice() {
+        [] => Err(format!(
+            "{} does not contain {} {}",
+            lock_path.display(),
+            package,
+            version.unwrap_or_default()
+        )),
+        [locked] => Ok(locked.clone()),
+        _ => Err(format!(
+            "{} contains several versions of {} ({}), use --version to pick one",
+            lock_path.display(),
+            package,
+            versions.join(", ")
+        )),
+    }
+}
+
+/// Returns the versions of `package` required by the manifests, sorted.
+fn declared_versions(
+    root_dir: &Path,
+    package: &str,
+    options: &Options,
+) -> Result<Vec<String>, String> {
+    let options = Options {
+        any_version: true,
+        ..options.clone()
+    };
+    let mut versions = vec![];
+    for manifest_file in get_manifest_files(root_dir, package, &options)?.0 {
+        let content = fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+        for (_, old) in outdated_dependencies(&content, package, "*", &options) {
+            versions.push(old.value);
+        }
+    }
+    versions.sort_unstable();
+    versions.dedup();
+    Ok(versions)
+}
+
+/// Sets the git reference (`tag`, `rev` or `branch`) of the git declarations of `package`.
+/// Members inheriting the dependency from `[workspace.dependencies]` are left alone.
+fn update_git_refs(
+    root_dir: &Path,
+    package: &str,
+    new_ref: &str,
+    options: &Options,
+) -> Result<Output, String> {
+    let (manifest_files, skipped_manifests) = get_manifest_files(root_dir, package, options)?;
+
+    let mut output = Output {
+        skipped_manifests,
+        ..Output::default()
+    };
+    let mut staged = vec![];
+    for manifest_file in manifest_files {
+        let content = fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+        let mut edits = vec![];
+        for dependency in manifest::dependencies(&content) {
+            if !is_package(&dependency, package, options) {
+                continue;
+            }
+            if dependency.workspace {
+                if !output.inherited_manifests.contains(&manifest_file) {
+                    output.inherited_manifests.push(manifest_file.clone());
+                }
+                continue;
+            }
+            match (&dependency.git, dependency.git_ref) {
+                (Some(_), Some((field, old))) if old.value != new_ref => {
+                    output.updated_declarations.push(Declaration {
+                        manifest: manifest_file.clone(),
+                        table: dependency.table.join("."),
+                        version: format!("{} {}", field, old.value),
+                    });
+                    edits.push((old.span, new_ref.to_string()));
+                }
+                (Some(_), None) => eprintln!(
+                    "warning: {} has no tag, rev or branch in {}, leaving it alone",
+                    package,
+                    manifest_file.display()
+                ),
+                _ => (),
+            }
+        }
+        if !edits.is_empty() {
+            staged.push((manifest_file.clone(), manifest::replace(&content, edits)));
+            output.originals.push((manifest_file.clone(), content));
+            output.updated_manifests.push(manifest_file);
+        }
+    }
+    write_manifests(&staged, options)?;
+
+    // the Cargo.lock pins the revision of git dependencies
+    if options.discover_glob.is_none() && !output.updated_manifests.is_empty() {
+        output.lock_update = update_cargo_lock(root_dir, &[package.to_string()], options);
+    }
+
+    Ok(output)
+}
+
+/// Parses a `name=version` specification, making sure the version is a valid requirement.
+fn parse_spec(spec: &str) -> Result<(String, String), String> {
+    let (name, version) = spec
+        .split_once('=')
+        .ok_or_else(|| format!("expected NAME=VERSION, got {}", spec))?;
+    let (name, version) = (name.trim(), version.trim());
+    semver::VersionReq::parse(version)
+        .map_err(|err| format!("invalid version {} for {}: {}", version, name, err))?;
+    Ok((name.to_string(), version.to_string()))
+}
+
+/// Brings each package of `specs` to its new version, whatever its current version is,
+/// updating each manifest once and running `cargo update` once.
+fn run_batch(
+    root_dir: &Path,
+    specs: &[(String, String)],
+    options: &Options,
+) -> Result<BatchOutput, String> {
+    let options = Options {
+        any_version: true,
+        ..options.clone()
+    };
+
+    // 1. fetch the manifests to update for each package
+    let mut manifest_files: Vec<(PathBuf, Vec<&(String, String)>)> = vec![];
+    for spec in specs {
+        for manifest_file in get_manifest_files(root_dir, &spec.0, &options)?.0 {
+            match manifest_files
+                .iter_mut()
+                .find(|(file, _)| file == &manifest_file)
+            {
+                Some((_, file_specs)) => file_specs.push(spec),
+                None => manifest_files.push((manifest_file, vec![spec])),
+            }
+        }
+    }
+
+    // 2. apply all the specs to each manifest
+    let mut output = BatchOutput::default();
+    let mut staged = vec![];
+    for (manifest_file, file_specs) in manifest_files {
+        let content = fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+        let mut new_content = content.clone();
+        for (package, new_version) in file_specs {
+            for (_, old) in outdated_dependencies(&new_content, package, "*", &options) {
+                let to = if options.preserve_version_prefix {
+                    with_prefix_of(&old.value, new_version)
+                } else {
+                    new_version.clone()
+                };
+                if old.value != to {
+                    output.transitions.push(Transition {
+                        manifest: manifest_file.clone(),
+                        package: package.clone(),
+                        from: old.value,
+                        to,
+                    });
+                }
+            }
+            if let Some(updated) = update_manifest(
+                &manifest_file,
+                &new_content,
+                package,
+                "*",
+                new_version,
+                &options,
+            ) {
+                new_content = updated;
+            }
+        }
+        if new_content != content {
+            staged.push((manifest_file.clone(), new_content));
+            output.updated_manifests.push(manifest_file);
+        }
+    }
+    write_manifests(&staged, &options)?;
+
+    // 3. update Cargo.lock with a single `cargo update`
+    if options.discover_glob.is_none() && !output.updated_manifests.is_empty() {
+        let pkgids = transition_pkgids(root_dir, &output.transitions);
+        output.lock_update = update_cargo_lock(root_dir, &pkgids, &options);
+    }
+
+    Ok(output)
+}
+
+/// Returns the package ids to pass to `cargo update` after `transitions`: the locked versions
+/// admitted by the old requirements, as several versions of a package can be locked.
+/// Packages without such a locked version are named alone.
+fn transition_pkgids(root_dir: &Path, transitions: &[Transition]) -> Vec<String> {
+    let mut pkgids = vec![];
+    for transition in transitions {
+        let pkgid = match check_lock(root_dir, &transition.package, &transition.from) {
+            Ok(Some(locked_version)) => format!("{}:{}", transition.package, locked_version),
+            _ => transition.package.clone(),
+        };
+        if !pkgids.contains(&pkgid) {
+            pkgids.push(pkgid);
+        }
+    }
+    pkgids
+}
+
+/// Output of `--set`, `--update-all-compatible` and `--all-direct-deps`.
+#[derive(serde::Serialize, Default)]
+struct BatchOutput {
+    updated_manifests: Vec<PathBuf>,
+    transitions: Vec<Transition>,
+    /// what happened to each dependency looked up in the registry
+    #[cfg(feature = "network")]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    outcomes: Vec<DependencyOutcome>,
+    lock_update: Option<LockUpdate>,
+}
+
+/// What happened to a dependency looked up in the registry.
+#[cfg(feature = "network")]
+#[derive(serde::Serialize)]
+struct DependencyOutcome {
+    package: String,
+    outcome: Outcome,
+}
+
+#[cfg(feature = "network")]
+#[derive(serde::Serialize, Debug, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Outcome {
+    /// some requirements were raised
+    Updated,
+    /// the requirements already are at the newest versions they allow
+    UpToDate,
+    /// the manifests declaring it were left alone (ignore rules, per-crate settings)
+    Skipped,
+    /// the registry couldn't be queried
+    Unavailable,
+}
+
+/// A version requirement that was changed.
+#[derive(serde::Serialize)]
+struct Transition {
+    manifest: PathBuf,
+    package: String,
+    from: String,
+    to: String,
+}
+
+/// Bumps every requirement on a crates.io dependency to the newest version it allows,
+/// only considering the dependencies matching the glob `pattern` if given.
+#[cfg(feature = "network")]
+fn update_all_compatible(
+    root_dir: &Path,
+    pattern: Option<&str>,
+    index_url: &str,
+    options: &Options,
+) -> Result<BatchOutput, String> {
+    let names = crates_io_dependencies(root_dir, pattern, &[], options)?;
+    update_to_newest_compatible(root_dir, &names, index_url, options)
+}
+
+/// Bumps the requirements on the direct crates.io dependencies of the workspace members
+/// (of the kinds in `options.dep_kinds`, or normal ones by default) to the newest versions
+/// they allow, only considering the dependencies matching the glob `pattern` if given.
+#[cfg(feature = "network")]
+fn update_direct_dependencies(
+    root_dir: &Path,
+    pattern: Option<&str>,
+    index_url: &str,
+    options: &Options,
+) -> Result<BatchOutput, String> {
+    let kinds = if options.dep_kinds.is_empty() {
+        vec!["normal".to_string()]
+    } else {
+        options.dep_kinds.clone()
+    };
+    let names = crates_io_dependencies(root_dir, pattern, &kinds, options)?;
+    update_to_newest_compatible(root_dir, &names, index_url, options)
+}
+
+/// Returns the names of the crates.io dependencies of the workspace members matching the glob
+/// `pattern` and `options.allowed_dependencies`, only keeping the given `kinds` if any.
+#[cfg(feature = "network")]
+fn crates_io_dependencies(
+    root_dir: &Path,
+    pattern: Option<&str>,
+    kinds: &[String],
+    options: &Options,
+) -> Result<Vec<String>, String> {
+    let pattern = glob::Pattern::new(pattern.unwrap_or("*"))
+        .map_err(|err| format!("invalid dependency name pattern: {}", err))?;
+    let allowed = options
+        .allowed_dependencies
+        .iter()
+        .map(|allowed| glob::Pattern::new(allowed))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| format!("invalid --allow-dependency pattern: {}", err))?;
+    let mut names: Vec<_> = get_workspace_members(get_metadata(root_dir)?)
+        .into_iter()
+        .flat_map(|member| member.dependencies)
+        .filter(|dependency| dependency.is_from_crates_io() && pattern.matches(&dependency.name))
+        .filter(|dependency| {
+            allowed.is_empty()
+                || allowed
+                    .iter()
+                    .any(|allowed| allowed.matches(&dependency.name))
+        })
+        .filter(|dependency| kinds.is_empty() || kinds.iter().any(|kind| kind == dependency.kind()))
+        .map(|dependency| dependency.name)
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    Ok(names)
+}
+
+/// Bumps the requirements on the crates.io dependencies `names` to the newest versions they
+/// allow, according to the index at `index_url`.
+#[cfg(feature = "network")]
+fn update_to_newest_compatible(
+    root_dir: &Path,
+    names: &[String],
+    index_url: &str,
+    options: &Options,
+) -> Result<BatchOutput, String> {
+    // offline, the index cache is all we have
+    if registry::is_offline() && options.index_cache.is_none() {
+        return Err(
+            "looking for the newest versions needs to query the registry, but cargo is offline"
+                .to_string(),
+        );
+    }
+    let options = Options {
+        any_version: true,
+        ..options.clone()
+    };
+
+    // 2. find the newest compatible version of each requirement
+    let mut output = BatchOutput::default();
+    let mut edits = vec![];
+    let mut updated_packages = vec![];
+    // a single pass over the registry for all the dependencies
+    let cache = options
+        .index_cache
+        .as_ref()
+        .map(|dir| registry::IndexCache {
+            dir: dir.clone(),
+            ttl: std::time::Duration::from_secs(options.index_cache_ttl),
+        });
+    let index_files =
+        registry::fetch_all(index_url, names, cache.as_ref(), registry::is_offline())?;
+    for (name, index_file) in names.iter().zip(index_files) {
+        let versions = match index_file {
+            Ok(content) => registry::versions(&content),
+            Err(err) => {
+                eprintln!("warning: {}, skipping {}", err, name);
+                output.outcomes.push(DependencyOutcome {
+                    package: name.clone(),
+                    outcome: Outcome::Unavailable,
+                });
+                continue;
+            }
+        };
+        let transitions = output.transitions.len();
+        let mut declared = false;
+        for manifest_file in get_manifest_files(root_dir, name, &options)?.0 {
+            let index = match edits.iter().position(|(file, _, _)| file == &manifest_file) {
+                Some(index) => index,
+                None => {
+                    let content =
+                        fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+                    edits.push((manifest_file.clone(), content, vec![]));
+                    edits.len() - 1
+                }
+            };
+            let (_, content, file_edits) = &mut edits[index];
+            for (_, old) in outdated_dependencies(content, name, "*", &options) {
+                declared = true;
+                // only caret and tilde requirements can be raised without changing their meaning
+                let bumpable = old
+                    .value
+                    .starts_with(|c: char| c.is_ascii_digit() || c == '^' || c == '~')
+                    && !old.value.contains(',');
+                let newest = match registry::newest_matching(&versions, &old.value) {
+                    Some(newest) if bumpable => newest,
+                    _ => continue,
+                };
+                let new_version = with_prefix_of(&old.value, &newest);
+                if new_version == old.value {
+                    continue;
+                }
+                output.transitions.push(Transition {
+                    manifest: manifest_file.clone(),
+                    package: name.clone(),
+                    from: old.value.clone(),
+                    to: new_version.clone(),
+                });
+                file_edits.push((old.span, new_version));
+                if !updated_packages.contains(name) {
+                    updated_packages.push(name.clone());
+                }
+            }
+        }
+        let outcome = if !declared {
+            Outcome::Skipped
+        } else if output.transitions.len() > transitions {
+            Outcome::Updated
+        } else {
+            Outcome::UpToDate
+        };
+        output.outcomes.push(DependencyOutcome {
+            package: name.clone(),
+            outcome,
+        });
+    }
+
+    // 3. update the manifests
+    let mut staged = vec![];
+    for (manifest_file, content, file_edits) in edits {
+        if !file_edits.is_empty() {
+            staged.push((
+                manifest_file.clone(),
+                manifest::replace(&content, file_edits),
+            ));
+            output.updated_manifests.push(manifest_file);
+        }
+    }
+    write_manifests(&staged, &options)?;
+
+    // 4. update Cargo.lock with a single `cargo update`
+    if options.discover_glob.is_none() && !updated_packages.is_empty() {
+        output.lock_update = update_cargo_lock(root_dir, &updated_packages, &options);
+    }
+
+    Ok(output)
+}
+
+/// Runs the update in every workspace found under `root_dir`,
+/// calling `on_result` with the result of each workspace as soon as it's done.
+fn run_recursive(
+    root_dir: &Path,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+    mut on_result: impl FnMut(WorkspaceOutput),
+) {
+    // each workspace is updated from its own root, so that `cargo update` uses its own Cargo.lock
+    for workspace in find_workspaces(root_dir) {
+        let metadata = get_metadata(&workspace).ok();
+        let result = lock_workspace(&workspace, metadata.as_ref(), !options.no_wait)
+            .and_then(|_lock| run(&workspace, package, version, new_version, options));
+        let (output, error) = match result {
+            Ok(output) => (Some(output), None),
+            Err(err) => (None, Some(err)),
+        };
+        let lock_file = output
+            .as_ref()
+            .and_then(|output| output.lock_update.as_ref())
+            .map(|_| get_lock_path(&workspace));
+        on_result(WorkspaceOutput {
+            workspace,
+            lock_file,
+            error,
+            output,
+        });
+    }
+}
+
+/// Returns the root directory of every workspace found under `root_dir`.
+fn find_workspaces(root_dir: &Path) -> Vec<PathBuf> {
+    fn find_manifests(dir: &Path, manifests: &mut Vec<PathBuf>) {
+        let manifest = dir.join("Cargo.toml");
+        if manifest.is_file() {
+            manifests.push(manifest);
+        }
+        let mut entries: Vec<_> = fs::read_dir(dir)
+            .map(|entries| entries.filter_map(Result::ok).map(|e| e.path()).collect())
+            .unwrap_or_default();
+        entries.sort();
+        for entry in entries {
+            let name = entry.file_name().unwrap_or_default().to_string_lossy();
+            if entry.is_dir() && name != "target" && !name.starts_with('.') {
+                find_manifests(&entry, manifests);
+            }
+        }
+    }
+    let mut manifests = vec![];
+    find_manifests(root_dir, &mut manifests);
+
+    // a manifest belongs to the workspace of the first manifest that lists it as a member
+    let mut workspaces = vec![];
+    let mut seen = vec![];
+    for manifest in manifests {
+        if seen.contains(&manifest) {
+            continue;
+        }
+        let dir = manifest.parent().expect("Failed to get manifest directory");
+        let metadata = match get_metadata(dir) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                eprintln!("warning: skipping {}: {}", manifest.display(), err);
+                continue;
+            }
+        };
+        seen.push(metadata.workspace_root.join("Cargo.toml"));
+        if !workspaces.contains(&metadata.workspace_root) {
+            workspaces.push(metadata.workspace_root.clone());
+        }
+        seen.extend(
+            get_workspace_members(metadata)
+                .into_iter()
+                .map(|member| member.manifest_path),
+        );
+    }
+    workspaces
+}
+
+/// Output of `--recursive`.
+#[derive(serde::Serialize)]
+struct RecursiveOutput {
+    workspaces: Vec<WorkspaceOutput>,
+}
+
+/// The result of the update of one of the workspaces, with `--recursive`.
+#[derive(serde::Serialize)]
+struct WorkspaceOutput {
+    workspace: PathBuf,
+    /// the Cargo.lock updated for this workspace
+    #[serde(skip_serializing_if = "Option::is_none")]
+    lock_file: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    #[serde(flatten)]
+    output: Option<Output>,
+}
+
+/// Updates the manifests of the workspace, leaving the Cargo.lock untouched.
+fn update_manifests(
+    root_dir: &Path,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+) -> Result<Output, String> {
+    let mut staged = vec![];
+    let output = stage_manifests(
+        root_dir,
+        package,
+        version,
+        new_version,
+        options,
+        &mut staged,
+    )?;
+    write_manifests(&staged, options)?;
+    Ok(output)
+}
+
+/// Adds the new content of the manifests of the workspace to `staged`, without writing them.
+/// The manifests already in `staged` are updated from their staged content.
+fn stage_manifests(
+    root_dir: &Path,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+    staged: &mut Vec<(PathBuf, String)>,
+) -> Result<Output, String> {
+    // 1. fetch all Cargo.toml file via `cargo metadata | jq '.workspace_members'`
+    let (manifest_files, skipped_manifests) = get_manifest_files(root_dir, package, options)?;
+
+    // 2. update them, potentially + keep track of which ones were updated
+    let mut output = Output {
+        skipped_manifests,
+        ..Output::default()
+    };
+    let mut declared_by_workspace = None;
+    let mut explicit = vec![];
+    for manifest_file in manifest_files {
+        let content = match staged.iter().find(|(path, _)| path == &manifest_file) {
+            Some((_, content)) => content.clone(),
+            None => fs::read_to_string(&manifest_file).expect("Failed to open manifest file"),
+        };
+        let new_content = update_manifest(
+            &manifest_file,
+            &content,
+            package,
+            version,
+            new_version,
+            options,
+        );
+
+        // keep track of the updated declarations, reporting the aliases of the package
+        if new_content.is_some() {
+            for (dependency, old) in outdated_dependencies(&content, package, version, options) {
+                if dependency.package.is_some() && dependency.key != dependency.name() {
+                    output.updated_aliases.push(Alias {
+                        manifest: manifest_file.clone(),
+                        key: dependency.key.clone(),
+                    });
+                }
+                output.updated_declarations.push(Declaration {
+                    manifest: manifest_file.clone(),
+                    table: dependency.table.join("."),
+                    version: old.value,
+                });
+            }
+        }
+
+        // keep track of how members get their version of the package
+        let final_content = new_content.as_deref().unwrap_or(&content);
+        for dependency in manifest::dependencies(final_content) {
+            if !is_package(&dependency, package, options) {
+                continue;
+            }
+
+            // there is no version to bump, the git reference has to be updated instead
+            if dependency.git.is_some() && dependency.version.is_none() {
+                if !output.unversioned_git_manifests.contains(&manifest_file) {
+                    eprintln!(
+                        "warning: {} is a git dependency without a version in {}, use --git-ref to update its tag, rev or branch",
+                        package,
+                        manifest_file.display()
+                    );
+                    output.unversioned_git_manifests.push(manifest_file.clone());
+                }
+                continue;
+            }
+
+            if dependency.in_workspace_table() {
+                declared_by_workspace = Some(dependency.version.map(|version| version.value));
+            } else if dependency.workspace {
+                if !output.inherited_manifests.contains(&manifest_file) {
+                    output.inherited_manifests.push(manifest_file.clone());
+                }
+            } else {
+                if new_content.is_some() && !explicit.contains(&manifest_file) {
+                    explicit.push(manifest_file.clone());
+                }
+
+                // the root manifest comes first, so we know the version of the workspace by now
+                if let (Some(Some(workspace_version)), Some(version)) =
+                    (&declared_by_workspace, &dependency.version)
+                {
+                    if &version.value != workspace_version
+                        && !output.diverging_manifests.contains(&manifest_file)
+                    {
+                        eprintln!(
+                            "warning: {} requires {} {}, while the workspace declares {}",
+                            manifest_file.display(),
+                            package,
+                            version.value,
+                            workspace_version,
+                        );
+                        output.diverging_manifests.push(manifest_file.clone());
+                    }
+                }
+            }
+        }
+
+        if let Some(new_content) = new_content {
+            match staged.iter_mut().find(|(path, _)| path == &manifest_file) {
+                Some((_, staged_content)) => *staged_content = new_content,
+                None => staged.push((manifest_file.clone(), new_content)),
+            }
+            output.originals.push((manifest_file.clone(), content));
+            output.updated_manifests.push(manifest_file);
+        }
+    }
+
+    // members with their own version only override the workspace if it declares the package
+    if declared_by_workspace.is_some() {
+        output.overridden_manifests = explicit;
+    }
+
+    Ok(output)
+}
+
+/// Writes the new content of the manifests.
+fn write_manifests(files: &[(PathBuf, String)], options: &Options) -> Result<(), String> {
+    if options.require_clean_git {
+        let paths: Vec<_> = files.iter().map(|(path, _)| path.clone()).collect();
+        ensure_clean_git(&paths)?;
+    }
+    if options.transactional {
+        return write_all_or_nothing(files);
+    }
+    for (path, content) in files {
+        fs::write(path, content)
+            .map_err(|err| format!("failed to update {}: {}", path.display(), err))?;
+    }
+    Ok(())
+}
+
+/// Writes every file, or none of them if one of them can't be written.
+/// The new contents are first written next to the files, and only then moved into place:
+/// if a move fails, the files already moved get their original content back.
+fn write_all_or_nothing(files: &[(PathBuf, String)]) -> Result<(), String> {
+    let staging_paths: Vec<_> = files
+        .iter()
+        .map(|(path, _)| {
+            let mut staging_path = path.clone().into_os_string();
+            staging_path.push(".cargo-update-dep");
+            PathBuf::from(staging_path)
+        })
+        .collect();
+    let remove_staged = || {
+        for staging_path in &staging_paths {
+            let _ = fs::remove_file(staging_path);
+        }
+    };
+
+    // files that don't exist yet are removed on failure
+    let originals: Vec<_> = files.iter().map(|(path, _)| fs::read(path).ok()).collect();
+
+    for ((_, content), staging_path) in files.iter().zip(&staging_paths) {
+        if let Err(err) = fs::write(staging_path, content) {
+            remove_staged();
+            return Err(format!(
+                "failed to stage {}, no manifest was modified: {}",
+                staging_path.display(),
+                err
+            ));
+        }
+    }
+
+    for (moved, ((path, _), staging_path)) in files.iter().zip(&staging_paths).enumerate() {
+        if let Err(err) = fs::rename(staging_path, path) {
+            for ((path, _), original) in files.iter().zip(&originals).take(moved) {
+                let _ = match original {
+                    Some(original) => fs::write(path, original),
+                    None => fs::remove_file(path),
+                };
+            }
+            remove_staged();
+            return Err(format!(
+                "failed to update {}, no manifest was modified: {}",
+                path.display(),
+                err
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Reports the manifests that still depend on `version` of `package`, without modifying anything.
+fn check(
+    root_dir: &Path,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+) -> Result<CheckOutput, String> {
+    let mut out_of_date = vec![];
+    let mut occurrences = vec![];
+    for manifest_file in get_manifest_files(root_dir, package, options)?.0 {
+        let content = fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+        if update_manifest(
+            &manifest_file,
+            &content,
+            package,
+            version,
+            new_version,
+            options,
+        )
+        .is_none()
+        {
+            continue;
+        }
+
+        for (_, old) in outdated_dependencies(&content, package, version, options) {
+            occurrences.push(Occurrence {
+                manifest: manifest_file.clone(),
+                line: manifest::line_of(&content, old.span.start) + 1,
+            });
+        }
+        out_of_date.push(manifest_file);
+    }
+
+    Ok(CheckOutput {
+        up_to_date: out_of_date.is_empty(),
+        out_of_date_manifests: out_of_date,
+        occurrences,
+    })
+}
+
+/// The changes the update would make to a manifest.
+struct FileDiff {
+    /// the path of the manifest, relative to the root directory
+    path: PathBuf,
+    /// groups of lines, each prefixed with `-`, `+` or ` ` (or a table header)
+    hunks: Vec<Vec<String>>,
+}
+
+/// Returns the changes the update would make, without modifying anything.
+/// The `scope` of each change is the changed `line` (with `context` lines around it),
+/// the dependency declaration with its `table`, or the whole `file`.
+fn diff(
+    root_dir: &Path,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+    scope: &str,
+    context: usize,
+) -> Result<Vec<FileDiff>, String> {
+    let mut diffs = vec![];
+    for manifest_file in get_manifest_files(root_dir, package, options)?.0 {
+        let content = fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+        let new_content = match update_manifest(
+            &manifest_file,
+            &content,
+            package,
+            version,
+            new_version,
+            options,
+        ) {
+            Some(new_content) => new_content,
+            None => continue,
+        };
+
+        // normalized inline tables can span fewer lines, so the lines are matched with a diff
+        let lines: Vec<_> = content.lines().collect();
+        let new_lines: Vec<_> = new_content.lines().collect();
+        let changes = line_changes(&lines, &new_lines);
+        let mut hunks = vec![];
+        if scope == "table" {
+            for (dependency, _) in outdated_dependencies(&content, package, version, options) {
+                let first = manifest::line_of(&content, dependency.span.start);
+                let last = manifest::line_of(&content, dependency.span.end);
+                let new_first = new_position(&changes, first, true);
+                let new_end = new_position(&changes, last + 1, false);
+                let mut hunk = vec![];
+                // declarations in their own table already start with the header
+                if !lines[first].trim_start().starts_with('[') {
+                    hunk.push(format!("[{}]", dependency.table.join(".")));
+                }
+                hunk.extend(lines[first..=last].iter().map(|line| format!("-{}", line)));
+                hunk.extend(
+                    new_lines[new_first..new_end]
+                        .iter()
+                        .map(|line| format!("+{}", line)),
+                );
+                hunks.push(hunk);
+            }
+        } else {
+            let context = if scope == "file" {
+                lines.len()
+            } else {
+                context
+            };
+            for hunk in group_changes(&changes, lines.len(), context) {
+                let hunk = hunk
+                    .lines()
+                    .into_iter()
+                    .map(|(prefix, index)| match prefix {
+                        '+' => format!("+{}", new_lines[index]),
+                        _ => format!("{}{}", prefix, lines[index]),
+                    })
+                    .collect();
+                hunks.push(hunk);
+            }
+        }
+
+        diffs.push(FileDiff {
+            path: relative_to_root(root_dir, &manifest_file)
+                .unwrap_or(&manifest_file)
+                .to_path_buf(),
+            hunks,
+        });
+    }
+    Ok(diffs)
+}
+
+/// A block of changed lines: the lines `old` of a content are replaced by the lines `new`.
+#[derive(Debug, PartialEq)]
+struct LineChange {
+    old: std::ops::Range<usize>,
+    new: std::ops::Range<usize>,
+}
+
+/// Returns the blocks of lines that differ between `lines` and `new_lines`,
+/// the other lines being their longest common subsequence.
+fn line_changes(lines: &[&str], new_lines: &[&str]) -> Vec<LineChange> {
+    // the length of the longest common subsequence of each pair of suffixes
+    let mut common = vec![vec![0; new_lines.len() + 1]; lines.len() + 1];
+    for index in (0..lines.len()).rev() {
+        for new_index in (0..new_lines.len()).rev() {
+            common[index][new_index] = if lines[index] == new_lines[new_index] {
+                common[index + 1][new_index + 1] + 1
+            } else {
+                common[index + 1][new_index].max(common[index][new_index + 1])
+            };
+        }
+    }
+
+    let mut changes: Vec<LineChange> = vec![];
+    let (mut index, mut new_index) = (0, 0);
+    while index < lines.len() || new_index < new_lines.len() {
+        if index < lines.len()
+            && new_index < new_lines.len()
+            && lines[index] == new_lines[new_index]
+        {
+            index += 1;
+            new_index += 1;
+            continue;
+        }
+        let (start, new_start) = (index, new_index);
+        // removed lines come before added ones
+        if new_index == new_lines.len()
+            || (index < lines.len() && common[index + 1][new_index] >= common[index][new_index + 1])
+        {
+            index += 1;
+        } else {
+            new_index += 1;
+        }
+        match changes.last_mut() {
+            Some(change) if change.old.end == start && change.new.end == new_start => {
+                change.old.end = index;
+                change.new.end = new_index;
+            }
+            _ => changes.push(LineChange {
+                old: start..index,
+                new: new_start..new_index,
+            }),
+        }
+    }
+    changes
+}
+
+/// Returns the position in the new lines of the position `index` in the old lines.
+/// If it falls inside a change, that's the `start` or the end of the new lines of the change.
+fn new_position(changes: &[LineChange], index: usize, start: bool) -> usize {
+    let mut position = index;
+    for change in changes {
+        if change.old.end <= index {
+            position = position - change.old.len() + change.new.len();
+        } else if change.old.start < index {
+            return if start {
+                change.new.start
+            } else {
+                change.new.end
+            };
+        }
+    }
+    position
+}
+
+/// Changes grouped with the unchanged lines around them.
+struct Hunk<'a> {
+    /// the lines of the hunk in the old content
+    old: std::ops::Range<usize>,
+    /// the lines of the hunk in the new content
+    new: std::ops::Range<usize>,
+    changes: &'a [LineChange],
+}
+
+impl Hunk<'_> {
+    /// Returns the lines of the hunk, as `' '` or `'-'` with the index of an old line,
+    /// or `'+'` with the index of a new line.
+    fn lines(&self) -> Vec<(char, usize)> {
+        let mut lines = vec![];
+        let mut index = self.old.start;
+        for change in self.changes {
+            lines.extend((index..change.old.start).map(|index| (' ', index)));
+            lines.extend(change.old.clone().map(|index| ('-', index)));
+            lines.extend(change.new.clone().map(|index| ('+', index)));
+            index = change.old.end;
+        }
+        lines.extend((index..self.old.end).map(|index| (' ', index)));
+        lines
+    }
+}
+
+/// Groups the `changes` of a content of `len` lines into hunks, with `context` unchanged lines
+/// around each change, merging the hunks that overlap or touch.
+fn group_changes(changes: &[LineChange], len: usize, context: usize) -> Vec<Hunk<'_>> {
+    let mut hunks: Vec<Hunk> = vec![];
+    for (index, change) in changes.iter().enumerate() {
+        let start = change.old.start.saturating_sub(context);
+        let end = (change.old.end + context).min(len);
+        // unchanged lines are shifted by the changes before them
+        let new_start = start + change.new.start - change.old.start;
+        let new_end = end + change.new.end - change.old.end;
+        match hunks.last_mut() {
+            Some(hunk) if start <= hunk.old.end => {
+                hunk.old.end = end;
+                hunk.new.end = new_end;
+                hunk.changes = &changes[index - hunk.changes.len()..=index];
+            }
+            _ => hunks.push(Hunk {
+                old: start..end,
+                new: new_start..new_end,
+                changes: &changes[index..=index],
+            }),
+        }
+    }
+    hunks
+}
+
+/// Returns the changes the update would make as a unified diff that `git apply` accepts,
+/// with paths relative to `root_dir`, without modifying anything.
+fn patch(
+    root_dir: &Path,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+) -> Result<String, String> {
+    let mut patch = String::new();
+    for manifest_file in get_manifest_files(root_dir, package, options)?.0 {
+        let content = fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+        let new_content = match update_manifest(
+            &manifest_file,
+            &content,
+            package,
+            version,
+            new_version,
+            options,
+        ) {
+            Some(new_content) => new_content,
+            None => continue,
+        };
+        let path = relative_to_root(root_dir, &manifest_file)
+            .map(relative_uri)
+            .ok_or_else(|| {
+                format!(
+                    "{} is outside of {}, it can't be part of the patch",
+                    manifest_file.display(),
+                    root_dir.display()
+                )
+            })?;
+        patch.push_str(&format!(
+            "diff --git a/{} b/{}\n--- a/{}\n+++ b/{}\n",
+            path, path, path, path
+        ));
+
+        let lines: Vec<_> = content.lines().collect();
+        let new_lines: Vec<_> = new_content.lines().collect();
+        // each line is followed by a newline, except maybe the last one
+        let line_of = |lines: &[&str], content: &str, prefix: char, index: usize| {
+            let mut line = format!("{}{}\n", prefix, lines[index]);
+            if index + 1 == lines.len() && !content.ends_with('\n') {
+                line.push_str("\\ No newline at end of file\n");
+            }
+            line
+        };
+
+        for hunk in group_changes(&line_changes(&lines, &new_lines), lines.len(), 3) {
+            // empty ranges start after the line before them
+            let position = |range: &std::ops::Range<usize>| match range.len() {
+                0 => range.start,
+                _ => range.start + 1,
+            };
+            patch.push_str(&format!(
+                "@@ -{},{} +{},{} @@\n",
+                position(&hunk.old),
+                hunk.old.len(),
+                position(&hunk.new),
+                hunk.new.len()
+            ));
+            for (prefix, index) in hunk.lines() {
+                match prefix {
+                    '+' => patch.push_str(&line_of(&new_lines, &new_content, '+', index)),
+                    _ => patch.push_str(&line_of(&lines, &content, prefix, index)),
+                }
+            }
+        }
+    }
+    Ok(patch)
+}
+
+/// Renders the changes with a header per file and a blank line between hunks,
+/// with ANSI colors if `color` is set.
+fn render_diff(diffs: &[FileDiff], color: bool) -> String {
+    let paint = |code: &str, line: &str| {
+        if color {
+            format!("\x1b[{}m{}\x1b[0m\n", code, line)
+        } else {
+            format!("{}\n", line)
+        }
+    };
+    let mut rendered = String::new();
+    for diff in diffs {
+        let path = diff.path.display();
+        rendered.push_str(&paint("1", &format!("--- {}", path)));
+        rendered.push_str(&paint("1", &format!("+++ {}", path)));
+        for (index, hunk) in diff.hunks.iter().enumerate() {
+            if index > 0 {
+                rendered.push('\n');
+            }
+            for line in hunk {
+                let line = match line.chars().next() {
+                    Some('-') => paint("31", line),
+                    Some('+') => paint("32", line),
+                    Some('[') => paint("36", line),
+                    _ => format!("{}\n", line),
+                };
+                rendered.push_str(&line);
+            }
+        }
+    }
+    rendered
+}
+
+/// Should the output be colored, according to `--color`?
+fn use_color(when: Option<&str>) -> bool {
+    use std::io::IsTerminal;
+
+    match when {
+        Some("always") => true,
+        Some("never") => false,
+        _ => std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal(),
+    }
+}
+
+/// Output of `--check`.
+#[derive(serde::Serialize)]
+struct CheckOutput {
+    up_to_date: bool,
+    out_of_date_manifests: Vec<PathBuf>,
+    /// where the outdated versions are declared
+    #[serde(skip)]
+    occurrences: Vec<Occurrence>,
+}
+
+/// The location of an outdated version requirement.
+struct Occurrence {
+    manifest: PathBuf,
+    /// 1-indexed line
+    line: usize,
+}
+
+/// Returns a GitHub Actions workflow command annotating the outdated version requirement, located
+/// relative to the `repository` root.
+fn github_annotation(
+    repository: &Path,
+    occurrence: &Occurrence,
+    package: &str,
+    version: &str,
+    new_version: &str,
+) -> String {
+    // git reports the canonical path of the repository
+    let repository = fs::canonicalize(repository).unwrap_or_else(|_| repository.to_path_buf());
+    let manifest =
+        fs::canonicalize(&occurrence.manifest).unwrap_or_else(|_| occurrence.manifest.clone());
+    let path = manifest.strip_prefix(&repository).unwrap_or(&manifest);
+    format!(
+        "::warning file={},line={}::{} is out of date ({}, latest {})",
+        path.display(),
+        occurrence.line,
+        package,
+        version,
+        new_version
+    )
+}
+
+/// The rule of the results of `sarif_report`.
+const SARIF_RULE: &str = "outdated-dependency";
+
+/// Returns a SARIF 2.1.0 report with a result for every outdated version requirement,
+/// for code scanning tools.
+fn sarif_report(
+    root_dir: &Path,
+    occurrences: &[Occurrence],
+    package: &str,
+    version: &str,
+    new_version: &str,
+) -> serde_json::Value {
+    let results: Vec<_> = occurrences
+        .iter()
+        .map(|occurrence| {
+            // SARIF expects URIs, relative to the root of the sources if possible
+            let artifact_location = match relative_to_root(root_dir, &occurrence.manifest) {
+                Some(path) => {
+                    serde_json::json!({ "uri": relative_uri(path), "uriBaseId": "%SRCROOT%" })
+                }
+                None => {
+                    serde_json::json!({ "uri": format!("file://{}", occurrence.manifest.display()) })
+                }
+            };
+            serde_json::json!({
+                "ruleId": SARIF_RULE,
+                "level": "warning",
+                "message": {
+                    "text": format!("{} is out of date ({}, latest {})", package, version, new_version),
+                },
+                "locations": [{
+                    "physicalLocation": {
+                        "artifactLocation": artifact_location,
+                        "region": { "startLine": occurrence.line },
+                    },
+                }],
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "$schema": "https://json.schemastore.org/sarif-2.1.0.json",
+        "version": "2.1.0",
+        "runs": [{
+            "tool": {
+                "driver": {
+                    "name": "cargo-update-dep",
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "rules": [{
+                        "id": SARIF_RULE,
+                        "shortDescription": { "text": "A dependency requires an outdated version" },
+                    }],
+                },
+            },
+            "results": results,
+        }],
+    })
+}
+
+/// The result of an update, printed as JSON.
+/// Scripts parse it, so the fields are never renamed or removed, only added
+/// (see `STABLE_OUTPUT_KEYS` in the tests).
+#[derive(serde::Serialize, Default)]
+struct Output {
+    updated_manifests: Vec<PathBuf>,
+    /// members inheriting the package from `[workspace.dependencies]`
+    inherited_manifests: Vec<PathBuf>,
+    /// members overriding the version of `[workspace.dependencies]` with their own
+    overridden_manifests: Vec<PathBuf>,
+    /// members requiring another version than the one of `[workspace.dependencies]`
+    diverging_manifests: Vec<PathBuf>,
+    /// declarations updated under another key than the name of the package
+    updated_aliases: Vec<Alias>,
+    /// manifests declaring the package as a git dependency without a version
+    unversioned_git_manifests: Vec<PathBuf>,
+    /// with `--relaxed-match`, the versions that were updated instead of the given one
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    relaxed_versions: Vec<String>,
+    skipped_manifests: Vec<SkippedManifest>,
+    lock_update: Option<LockUpdate>,
+    /// with `--explain-resolution`, how the Cargo.lock resolves the package after the update
+    #[serde(skip_serializing_if = "Option::is_none")]
+    resolution: Option<Resolution>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git: Option<GitOutput>,
+    /// every declaration that was updated, for `--format renovate`
+    #[serde(skip)]
+    updated_declarations: Vec<Declaration>,
+    /// the content of the updated manifests before the update, for `--journal`
+    #[serde(skip)]
+    originals: Vec<(PathBuf, String)>,
+}
+
+/// The journal of a run, written by `--journal`.
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Journal {
+    package: String,
+    version: String,
+    new_version: String,
+    /// the files modified by the run, with their content before it
+    files: Vec<JournalFile>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct JournalFile {
+    path: PathBuf,
+    original: String,
+}
+
+/// A version requirement that was updated.
+struct Declaration {
+    manifest: PathBuf,
+    /// the table of the declaration, like `dev-dependencies`
+    table: String,
+    /// the version before the update
+    version: String,
+}
+
+/// Output of `--format renovate`, mirroring the conventions of Renovate.
+#[derive(serde::Serialize)]
+struct RenovateOutput {
+    updates: Vec<RenovateUpdate>,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RenovateUpdate {
+    dep_name: String,
+    current_value: String,
+    new_value: String,
+    /// relative to the manifest path directory
+    package_file: PathBuf,
+    dep_type: String,
+    datasource: &'static str,
+}
+
+impl RenovateOutput {
+    fn new(root_dir: &Path, output: &Output, package: &str, new_version: &str) -> Self {
+        let updates = output
+            .updated_declarations
+            .iter()
+            .map(|declaration| RenovateUpdate {
+                dep_name: package.to_string(),
+                current_value: declaration.version.clone(),
+                new_value: new_version.to_string(),
+                package_file: relative_to_root(root_dir, &declaration.manifest)
+                    .unwrap_or(&declaration.manifest)
+                    .to_path_buf(),
+                dep_type: declaration.table.clone(),
+                datasource: "crate",
+            })
+            .collect();
+        Self { updates }
+    }
+}
+
+/// A dependency renamed with `key = { package = "...", ... }`.
+#[derive(serde::Serialize)]
+struct Alias {
+    manifest: PathBuf,
+    key: String,
+}
+
+/// Result of `--commit`.
+#[derive(serde::Serialize)]
+struct GitOutput {
+    branch: Option<String>,
+    /// the hash of the commit, if there was something to commit
+    commit: Option<String>,
+    pushed: bool,
+}
+
+/// Output of an update with a glob pattern as dependency name.
+#[derive(serde::Serialize)]
+struct GlobOutput {
+    /// the dependencies that were updated
+    packages: Vec<String>,
+    /// the crates of the workspace left out by `--skip-workspace-crates`
+    skipped_workspace_crates: Vec<String>,
+    #[serde(flatten)]
+    output: Output,
+}
+
+/// A manifest that was not considered for the update.
+#[derive(serde::Serialize)]
+struct SkippedManifest {
+    manifest: PathBuf,
+    reason: SkipReason,
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum SkipReason {
+    /// opted out in its `[package.metadata.cargo-update-dep]`
+    PackageMetadata,
+    /// matched a pattern of `.cargoupdateignore` or `--exclude-path`
+    IgnoreRule,
+    /// not in the `default-members` of the workspace, with `--default-members-only`
+    NotDefaultMember,
+}
+
+/// How the Cargo.lock resolved a package, before and after the update.
+#[derive(serde::Serialize, Debug, PartialEq)]
+struct Resolution {
+    package: String,
+    /// the requirement written in the manifests
+    requirement: String,
+    /// the versions of the package in the Cargo.lock before the update
+    before: Vec<String>,
+    /// the versions of the package in the Cargo.lock after the update
+    after: Vec<String>,
+    /// the newest version matching the requirement after the update, which cargo picked for it
+    chosen: Option<String>,
+}
+
+/// Explains which version cargo chose for the `requirement` on `package`, by comparing the
+/// versions locked before the update (`lock`) and after it (`new_lock`).
+fn explain_resolution(package: &str, requirement: &str, lock: &str, new_lock: &str) -> Resolution {
+    let locked_versions = |content: &str| -> Vec<String> {
+        lockfile::packages(content)
+            .into_iter()
+            .filter(|locked| locked.name == package)
+            .map(|locked| locked.version)
+            .collect()
+    };
+    let after = locked_versions(new_lock);
+    // cargo picks the newest locked version matching a requirement
+    let chosen = semver::VersionReq::parse(requirement)
+        .ok()
+        .and_then(|requirement| {
+            after
+                .iter()
+                .filter_map(|version| semver::Version::parse(version).ok())
+                .filter(|version| requirement.matches(version))
+                .max()
+                .map(|version| version.to_string())
+        });
+    Resolution {
+        package: package.to_string(),
+        requirement: requirement.to_string(),
+        before: locked_versions(lock),
+        after,
+        chosen,
+    }
+}
+
+/// Result of the `cargo update` invocation.
+#[derive(serde::Serialize)]
+struct LockUpdate {
+    stdout: String,
+    stderr: String,
+    /// exit code of `cargo update` (`None` if it was killed by a signal)
+    status: Option<i32>,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct CargoMetadata {
+    packages: Vec<Package>,
+    workspace_members: Vec<String>,
+    /// only reported by cargo 1.71 and later
+    #[serde(default)]
+    workspace_default_members: Option<Vec<String>>,
+    workspace_root: PathBuf,
+    /// the `[workspace.metadata]` table
+    #[serde(default)]
+    metadata: Option<serde_json::Value>,
+    target_directory: PathBuf,
+}
+
+#[derive(serde::Deserialize, Clone)]
+struct Package {
+    id: String,
+    name: String,
+    manifest_path: PathBuf,
+    dependencies: Vec<Dependency>,
+    /// the `[package.metadata]` table
+    metadata: Option<serde_json::Value>,
+}
+
+impl Package {
+    /// Returns the settings of `[package.metadata.cargo-update-dep]`.
+    fn settings(&self) -> Settings {
+        self.metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get("cargo-update-dep"))
+            .map(|settings| {
+                serde_json::from_value(settings.clone()).unwrap_or_else(|err| {
+                    eprintln!(
+                        "warning: ignoring invalid [package.metadata.cargo-update-dep] in {}: {}",
+                        self.manifest_path.display(),
+                        err
+                    );
+                    Settings::default()
+                })
+            })
+            .unwrap_or_default()
+    }
+}
+
+/// Per-crate settings, read from `[package.metadata.cargo-update-dep]`.
+#[derive(serde::Deserialize, Default)]
+#[serde(default)]
+struct Settings {
+    /// never update this manifest
+    skip: bool,
+    /// never update these dependencies in this manifest
+    #[serde(deserialize_with = "one_or_many")]
+    pinned: Vec<String>,
+}
+
+/// Deserializes either a single string or a list of strings.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        OneOrMany::One(one) => vec![one],
+        OneOrMany::Many(many) => many,
+    })
+}
+
+/// A dependency as declared in a manifest (as reported by `cargo metadata`).
+#[derive(serde::Deserialize, Clone)]
+struct Dependency {
+    name: String,
+    rename: Option<String>,
+    /// `None` for normal dependencies, `"dev"` or `"build"` otherwise
+    kind: Option<String>,
+    /// the registry of the dependency, `None` for path and git dependencies
+    #[cfg(feature = "network")]
+    source: Option<String>,
+}
+
+impl Dependency {
+    #[cfg(feature = "network")]
+    fn is_from_crates_io(&self) -> bool {
+        self.source.as_deref() == Some("registry+https://github.com/rust-lang/crates.io-index")
+    }
+
+    fn kind(&self) -> &str {
+        self.kind.as_deref().unwrap_or("normal")
+    }
+}
+
+/// Returns the manifests to update, and the ones that were skipped.
+/// The workspace root comes first, as it can declare dependencies for its members.
+fn get_manifest_files(
+    root_dir: &Path,
+    package: &str,
+    options: &Options,
+) -> Result<(Vec<PathBuf>, Vec<SkippedManifest>), String> {
+    let (mut manifest_files, mut skipped) = find_manifest_files(root_dir, package, options)?;
+
+    // leave alone the manifests matching an ignore rule
+    let matcher = get_ignore_matcher(root_dir, &options.exclude_paths)?;
+    manifest_files.retain(|manifest_file| {
+        let ignored = manifest_file
+            .strip_prefix(root_dir)
+            .is_ok_and(|path| matcher.matched_path_or_any_parents(path, false).is_ignore());
+        if ignored {
+            skipped.push(SkippedManifest {
+                manifest: manifest_file.clone(),
+                reason: SkipReason::IgnoreRule,
+            });
+        }
+        !ignored
+    });
+
+    Ok((manifest_files, skipped))
+}
+
+/// Returns a matcher for the patterns of `root_dir/.cargoupdateignore` and `exclude_paths`,
+/// relative to `root_dir`.
+fn get_ignore_matcher(root_dir: &Path, exclude_paths: &[String]) -> Result<Gitignore, String> {
+    let mut builder = GitignoreBuilder::new(root_dir);
+    let ignore_file = root_dir.join(".cargoupdateignore");
+    if ignore_file.exists() {
+        if let Some(err) = builder.add(&ignore_file) {
+            return Err(format!("could not read {}: {}", ignore_file.display(), err));
+        }
+    }
+    for pattern in exclude_paths {
+        builder
+            .add_line(None, pattern)
+            .map_err(|err| format!("invalid --exclude-path {}: {}", pattern, err))?;
+    }
+    builder.build().map_err(|err| err.to_string())
+}
+
+/// Returns the manifests to update along with the ones that opted out, before ignore rules.
+fn find_manifest_files(
+    root_dir: &Path,
+    package: &str,
+    options: &Options,
+) -> Result<(Vec<PathBuf>, Vec<SkippedManifest>), String> {
+    if let Some(pattern) = &options.discover_glob {
+        return Ok((discover_manifest_files(root_dir, pattern), vec![]));
+    }
+
+    let metadata = match get_metadata(root_dir) {
+        Ok(metadata) => metadata,
+        Err(err) if options.strict => return Err(err),
+        Err(err) => {
+            eprintln!("warning: {}", err);
+            eprintln!("warning: falling back to the members listed in the root manifest, which may be incomplete");
+            return Ok((get_listed_manifest_files(root_dir), vec![]));
+        }
+    };
+    let root_manifest = metadata.workspace_root.join("Cargo.toml");
+
+    let default_members = if options.default_members_only {
+        let default_members = metadata.workspace_default_members.clone();
+        if default_members.is_none() {
+            eprintln!(
+                "warning: cargo doesn't report the default members, updating all the members"
+            );
+        }
+        default_members
+    } else {
+        None
+    };
+
+    // members can opt out via their `[package.metadata.cargo-update-dep]`
+    let mut skipped = vec![];
+    let mut members = get_workspace_members(metadata);
+    members.retain(|member| {
+        let non_default = default_members
+            .as_ref()
+            .is_some_and(|default_members| !default_members.contains(&member.id));
+        if non_default {
+            skipped.push(SkippedManifest {
+                manifest: member.manifest_path.clone(),
+                reason: SkipReason::NotDefaultMember,
+            });
+            return false;
+        }
+
+        let settings = member.settings();
+        if settings.skip || settings.pinned.iter().any(|pinned| pinned == package) {
+            skipped.push(SkippedManifest {
+                manifest: member.manifest_path.clone(),
+                reason: SkipReason::PackageMetadata,
+            });
+            return false;
+        }
+        true
+    });
+
+    let root_is_member = members
+        .iter()
+        .any(|member| member.manifest_path == root_manifest);
+    let mut manifest_files = filter_by_dep_kinds(members, package, &options.dep_kinds);
+
+    // the root manifest also holds the `[workspace.dependencies]` the members inherit,
+    // which are updated unless the root opted out
+    let root_skipped = skipped
+        .iter()
+        .any(|skipped| skipped.manifest == root_manifest);
+    let root_kept = manifest_files.contains(&root_manifest);
+    manifest_files.retain(|manifest_file| manifest_file != &root_manifest);
+    if root_kept
+        || (!root_skipped
+            && (!root_is_member || declares_workspace_dependency(&root_manifest, package, options)))
+    {
+        manifest_files.insert(0, root_manifest);
+    }
+    Ok((manifest_files, skipped))
+}
+
+/// Does the manifest at `path` declare `package` in its `[workspace.dependencies]`?
+fn declares_workspace_dependency(path: &Path, package: &str, options: &Options) -> bool {
+    let content = fs::read_to_string(path).unwrap_or_default();
+    manifest::dependencies(&content).iter().any(|dependency| {
+        dependency.in_workspace_table() && is_package(dependency, package, options)
+    })
+}
+
+/// Returns the root manifest and the manifests of the members listed in its `[workspace]`,
+/// without cargo.
+fn get_listed_manifest_files(root_dir: &Path) -> Vec<PathBuf> {
+    let root_manifest = root_dir.join("Cargo.toml");
+    let content = fs::read_to_string(&root_manifest).unwrap_or_default();
+    let members = manifest::string_array(&content, &["workspace", "members"]).unwrap_or_default();
+    let exclude = manifest::string_array(&content, &["workspace", "exclude"]).unwrap_or_default();
+
+    let mut manifest_files = vec![root_manifest];
+    for member in members {
+        for manifest_file in discover_manifest_files(root_dir, &format!("{}/Cargo.toml", member)) {
+            let excluded = exclude
+                .iter()
+                .any(|exclude| manifest_file.starts_with(root_dir.join(exclude)));
+            if !excluded && !manifest_files.contains(&manifest_file) {
+                manifest_files.push(manifest_file);
+            }
+        }
+    }
+    manifest_files
+}
+
+/// Returns the manifests matching the glob `pattern` (relative to `root_dir`), without cargo.
+fn discover_manifest_files(root_dir: &Path, pattern: &str) -> Vec<PathBuf> {
+    let pattern = root_dir.join(pattern);
+    let pattern = pattern
+        .to_str()
+        .expect("Failed to convert glob pattern to string");
+    glob::glob(pattern)
+        .expect("Failed to parse glob pattern")
+        .map(|path| path.expect("Failed to read discovered path"))
+        .filter(|path| path.is_file())
+        .map(|path| normalize_path(&path))
+        .collect()
+}
+
+/// Resolves the `..` and `.` components of `path` without touching the filesystem,
+/// so that members listed as `../elsewhere` get the same path as in `cargo metadata`.
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            component => normalized.push(component),
+        }
+    }
+    normalized
+}
+
+/// The `cargo metadata` of the workspaces, by root directory. It stays valid across our own
+/// updates, which only change requirements, while `--watch` forgets it when manifests are edited.
+static METADATA: Mutex<Vec<(PathBuf, Result<CargoMetadata, String>)>> = Mutex::new(Vec::new());
+
+fn get_metadata(root_dir: &Path) -> Result<CargoMetadata, String> {
+    let cached = METADATA
+        .lock()
+        .expect("metadata cache poisoned")
+        .iter()
+        .find(|(dir, _)| dir == root_dir)
+        .map(|(_, metadata)| metadata.clone());
+    if let Some(metadata) = cached {
+        return metadata;
+    }
+
+    // run `cargo metadata`
+    let output = Command::new("cargo")
+        .current_dir(root_dir)
+        .args(["metadata", "--format-version", "1", "--no-deps"])
+        .output()
+        .expect("failed to execute process");
+    let metadata = if output.status.success() {
+        // json load the result
+        Ok(serde_json::from_slice(&output.stdout)
+            .expect("Failed to deserialize cargo metadata output"))
+    } else {
+        Err(format!(
+            "cargo metadata failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    };
+    METADATA
+        .lock()
+        .expect("metadata cache poisoned")
+        .push((root_dir.to_path_buf(), metadata.clone()));
+    metadata
+}
+
+/// Forgets the `cargo metadata` of the workspaces, once their manifests were edited.
+#[cfg(feature = "watch")]
+fn forget_metadata() {
+    METADATA.lock().expect("metadata cache poisoned").clear();
+}
+
+fn get_workspace_members(cargo_metadata: CargoMetadata) -> Vec<Package> {
+    // return every workspace member
+    let members = cargo_metadata.workspace_members;
+    cargo_metadata
+        .packages
+        .into_iter()
+        .filter(|package| members.contains(&package.id))
+        .collect()
+}
+
+/// Returns the manifests of the members that depend on `package` with one of the `dep_kinds`.
+/// If `dep_kinds` is empty, or if no member is known to depend on `package`
+/// (in which case we can't trust the metadata), all the manifests are returned.
+fn filter_by_dep_kinds(members: Vec<Package>, package: &str, dep_kinds: &[String]) -> Vec<PathBuf> {
+    let is_package =
+        |dep: &Dependency| dep.name == package || dep.rename.as_deref() == Some(package);
+
+    let known = members
+        .iter()
+        .flat_map(|member| &member.dependencies)
+        .any(is_package);
+    if !dep_kinds.is_empty() && !known {
+        eprintln!(
+            "warning: no workspace member declares {} according to cargo metadata, scanning all manifests",
+            package
+        );
+    }
+
+    members
+        .into_iter()
+        .filter(|member| {
+            dep_kinds.is_empty()
+                || !known
+                || member
+                    .dependencies
+                    .iter()
+                    .any(|dep| is_package(dep) && dep_kinds.iter().any(|kind| kind == dep.kind()))
+        })
+        .map(|member| member.manifest_path)
+        .collect()
+}
+
+/// Is `dependency` the package we're looking for (either by its key or by its package name)?
+fn is_package(dependency: &manifest::Dependency, package: &str, options: &Options) -> bool {
+    matching_name(dependency, package, options).is_some()
+}
+
+/// Returns the name through which `dependency` matches `package`, if it does.
+fn matching_name<'a>(
+    dependency: &'a manifest::Dependency,
+    package: &str,
+    options: &Options,
+) -> Option<&'a str> {
+    let matches =
+        |name: &str| name == package || (options.ignore_case && name.eq_ignore_ascii_case(package));
+    let key = Some(dependency.key.as_str()).filter(|_| !options.by_package_name);
+    [key, Some(dependency.name())]
+        .iter()
+        .flatten()
+        .copied()
+        .find(|name| matches(name))
+}
+
+/// Returns the name of the package that `package` designates in the Cargo.lock:
+/// the package itself, unless it's only the key of aliased dependencies
+/// (like `my_json = { package = "serde_json" }`).
+fn resolve_package_name(
+    root_dir: &Path,
+    package: &str,
+    options: &Options,
+) -> Result<String, String> {
+    let mut aliased = None;
+    for manifest_file in get_manifest_files(root_dir, package, options)?.0 {
+        let content = fs::read_to_string(&manifest_file).expect("Failed to open manifest file");
+        for dependency in manifest::dependencies(&content) {
+            if !is_package(&dependency, package, options) {
+                continue;
+            }
+            if dependency.name() == package {
+                return Ok(package.to_string());
+            }
+            aliased.get_or_insert_with(|| dependency.name().to_string());
+        }
+    }
+    Ok(aliased.unwrap_or_else(|| package.to_string()))
+}
+
+/// Returns the declarations of `package` requiring `version` (or any version with
+/// `options.any_version`) in the manifest, with their version.
+fn outdated_dependencies(
+    content: &str,
+    package: &str,
+    version: &str,
+    options: &Options,
+) -> Vec<(manifest::Dependency, manifest::Str)> {
+    manifest::dependencies(content)
+        .into_iter()
+        .filter(|dependency| is_package(dependency, package, options))
+        .filter(|dependency| !options.workspace_scope || dependency.in_workspace_table())
+        .filter(|dependency| {
+            options
+                .source
+                .as_ref()
+                .is_none_or(|source| source.declares(dependency))
+        })
+        .filter_map(|dependency| match dependency.version.clone() {
+            Some(old) if options.any_version || old.value == version => Some((dependency, old)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Returns the new content of the manifest, or `None` if it doesn't need to change.
+fn update_manifest(
+    manifest_path: &Path,
+    content: &str,
+    package: &str,
+    version: &str,
+    new_version: &str,
+    options: &Options,
+) -> Option<String> {
+    let mut edits = vec![];
+    for (dependency, old) in outdated_dependencies(content, package, version, options) {
+        let name = matching_name(&dependency, package, options).unwrap_or(package);
+
+        // cargo is case-sensitive, so this might not be the dependency the user meant
+        if name != package {
+            eprintln!(
+                "warning: updating {} in {} which only matches {} case-insensitively",
+                name,
+                manifest_path.display(),
+                package
+            );
+        }
+        let new_version = if options.preserve_version_prefix {
+            with_prefix_of(&old.value, new_version)
+        } else {
+            new_version.to_string()
+        };
+        edits.push((old.span.clone(), new_version));
+    }
+
+    // rewriting a version with itself is not an update
+    let mut new_content = manifest::replace(content, edits);
+    if new_content.as_bytes() == content.as_bytes() {
+        return None;
+    }
+
+    // the new versions moved the declarations around, so they are found again
+    if options.normalize_inline_tables {
+        let updated = outdated_dependencies(content, package, version, options);
+        let edits = manifest::dependencies(&new_content)
+            .into_iter()
+            .filter(|dependency| {
+                updated
+                    .iter()
+                    .any(|(old, _)| old.table == dependency.table && old.key == dependency.key)
+            })
+            .filter_map(|dependency| manifest::normalize_inline_table(&new_content, &dependency))
+            .collect();
+        new_content = manifest::replace(&new_content, edits);
+    }
+    Some(new_content)
+}
+
+/// Returns `new_version` with the operator of `old_version` (or without operator if it has none).
+/// Requirements made of several comparators are left as they are.
+fn with_prefix_of(old_version: &str, new_version: &str) -> String {
+    fn split_operator(version: &str) -> Option<(&str, &str)> {
+        if version.contains(',') {
+            return None;
+        }
+        let version = version.trim();
+        let operator_len = [">=", "<=", "^", "~", "=", ">", "<"]
+            .iter()
+            .find(|operator| version.starts_with(*operator))
+            .map_or(0, |operator| operator.len());
+        let (operator, rest) = version.split_at(operator_len);
+        Some((operator, rest.trim_start()))
+    }
+
+    match (split_operator(old_version), split_operator(new_version)) {
+        (Some((operator, _)), Some((_, version))) => format!("{}{}", operator, version),
+        _ => new_version.to_string(),
+    }
+}
+
+/// Runs `cargo update` on the given package id specifications.
+/// Returns `None` if there's no Cargo.lock and `--no-create-lock` was passed.
+fn update_cargo_lock(root_dir: &Path, pkgids: &[String], options: &Options) -> Option<LockUpdate> {
+    // `cargo update` generates a Cargo.lock if there's none, which libraries often don't want
+    let lock_path = get_lock_path(root_dir);
+    if !lock_path.exists() {
+        if options.no_create_lock {
+            eprintln!(
+                "warning: {} does not exist, skipping the lockfile update",
+                lock_path.display()
+            );
+            return None;
+        }
+        eprintln!(
+            "warning: {} does not exist, cargo update will create it (use --no-create-lock to avoid this)",
+            lock_path.display()
+        );
+    }
+
+    // run `cargo update`
+    let mut command = Command::new("cargo");
+    command.current_dir(root_dir).arg("update");
+    for pkgid in pkgids {
+        command.args(["-p", pkgid]);
+    }
+    let output = command.output().expect("failed to execute process");
+    // we don't assert on the status:
+    // this command might fail if the user is running something in parallel to update the Cargo.lock
+
+    let lock_update = LockUpdate {
+        stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        status: output.status.code(),
+    };
+
+    // echo for humans on stderr, stdout is reserved for the JSON output
+    eprint!("{}", lock_update.stdout);
+    eprint!("{}", lock_update.stderr);
+
+    Some(lock_update)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_everything() {
+        // first copy our Cargo.toml so we don't rewrite it
+        let mut src = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        src.push("resources/test");
+        let dst = tempfile::tempdir().unwrap().into_path();
+        fs::copy(
+            src.as_path().join("Cargo.toml"),
+            dst.as_path().join("Cargo.toml"),
+        )
+        .unwrap();
+        fs::create_dir(dst.as_path().join("src")).unwrap();
+        fs::File::create(dst.as_path().join("src/lib.rs")).unwrap();
+
+        // run on that Cargo.toml
+        let options = Options::default();
+        run(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        run(&dst, "serde_json", "1.0.60", "1.0.61", &options).unwrap();
+        run(&dst, "regex", "0.1.77", "1.4.3", &options).unwrap();
+        run(&dst, "lazy_static", "0.2.11", "1.4.0", &options).unwrap();
+
+        // check that it worked
+        let result = fs::read_to_string(dst.as_path().join("Cargo.toml")).unwrap();
+        let expected = fs::read_to_string(src.as_path().join("Cargo.toml.new")).unwrap();
+
+        assert!(result == expected);
+    }
+
+    /// Copies the fixture directory `resources/test/<name>` to a temporary directory.
+    fn copy_fixture(name: &str) -> PathBuf {
+        fn copy_dir(src: &Path, dst: &Path) {
+            fs::create_dir_all(dst).unwrap();
+            for entry in fs::read_dir(src).unwrap() {
+                let entry = entry.unwrap();
+                let dst = dst.join(entry.file_name());
+                if entry.file_type().unwrap().is_dir() {
+                    copy_dir(&entry.path(), &dst);
+                } else {
+                    fs::copy(entry.path(), dst).unwrap();
+                }
+            }
+        }
+
+        let mut src = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        src.push("resources/test");
+        src.push(name);
+        let dst = tempfile::tempdir().unwrap().into_path();
+        copy_dir(&src, &dst);
+        dst
+    }
+
+    /// Returns the path of the manifest of `dir` relative to the current directory.
+    fn relative_manifest_path(dir: &Path) -> PathBuf {
+        let cwd = std::env::current_dir().unwrap();
+        cwd.components()
+            .skip(1)
+            .map(|_| Component::ParentDir)
+            .chain(dir.components().skip(1))
+            .collect::<PathBuf>()
+            .join("Cargo.toml")
+    }
+
+    /// Updates `packages` from 0.1.1 to 0.2.0 in the manifest of the fixture `resources/test/<name>`,
+    /// and checks that the result is byte for byte its `Cargo.toml.new`.
+    fn assert_fixture_update(name: &str, packages: &[&str]) {
+        let mut src = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        src.push("resources/test");
+        src.push(name);
+        let content = fs::read_to_string(src.join("Cargo.toml")).unwrap();
+        let expected = fs::read_to_string(src.join("Cargo.toml.new")).unwrap();
+
+        let options = Options::default();
+        let mut result = content;
+        for package in packages {
+            result = update_manifest(&src, &result, package, "0.1.1", "0.2.0", &options).unwrap();
+        }
+        assert_eq!(result.as_bytes(), expected.as_bytes());
+    }
+
+    #[test]
+    fn test_dep_kinds() {
+        let dst = copy_fixture("dep_kinds");
+
+        let members = get_workspace_members(get_metadata(&dst).unwrap());
+        let manifests = filter_by_dep_kinds(members, "serde", &["dev".to_string()]);
+        assert_eq!(manifests, vec![dst.join("dev/Cargo.toml")]);
+
+        // falls back to all the manifests if no member declares the dependency
+        let members = get_workspace_members(get_metadata(&dst).unwrap());
+        let manifests = filter_by_dep_kinds(members, "rand", &["dev".to_string()]);
+        assert_eq!(manifests.len(), 2);
+    }
+
+    #[test]
+    fn test_root_dep_kinds() {
+        // a single-crate workspace, depending on serde as a normal dependency
+        let dst = copy_fixture("lock_check");
+
+        let options = Options {
+            dep_kinds: vec!["dev".to_string()],
+            ..Options::default()
+        };
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert!(output.updated_manifests.is_empty());
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"serde = "1.0.122""#));
+
+        let options = Options {
+            dep_kinds: vec!["normal".to_string()],
+            ..Options::default()
+        };
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(output.updated_manifests.len(), 1);
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let dst = copy_fixture("ignore_case");
+
+        // cargo is case-sensitive, so we are too by default
+        let options = Options::default();
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert!(output.updated_manifests.is_empty());
+
+        let options = Options {
+            ignore_case: true,
+            ..Options::default()
+        };
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(output.updated_manifests.len(), 1);
+
+        let result = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(result.contains(r#"Serde = "1.0.123""#));
+    }
+
+    #[test]
+    fn test_check() {
+        let dst = copy_fixture("dep_kinds");
+        let options = Options::default();
+
+        let output = check(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert!(!output.up_to_date);
+        assert_eq!(output.out_of_date_manifests.len(), 2);
+
+        // nothing was modified
+        let manifest = fs::read_to_string(dst.join("normal/Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"serde = "1.0.122""#));
+
+        let annotation =
+            github_annotation(&dst, &output.occurrences[0], "serde", "1.0.122", "1.0.123");
+        assert_eq!(
+            annotation,
+            "::warning file=normal/Cargo.toml,line=7::serde is out of date (1.0.122, latest 1.0.123)"
+        );
+
+        // the SARIF report is valid against the 2.1.0 schema
+        let report = sarif_report(&dst, &output.occurrences, "serde", "1.0.122", "1.0.123");
+        let schema = include_str!("../resources/test/sarif-schema-2.1.0.json");
+        let validator = jsonschema::validator_for(&serde_json::from_str(schema).unwrap()).unwrap();
+        let errors: Vec<_> = validator
+            .iter_errors(&report)
+            .map(|err| format!("{} at {}", err, err.instance_path))
+            .collect();
+        assert!(errors.is_empty(), "invalid SARIF report: {:?}", errors);
+        assert_eq!(report["version"], "2.1.0");
+        let run = &report["runs"][0];
+        assert_eq!(run["tool"]["driver"]["name"], "cargo-update-dep");
+        let rules = run["tool"]["driver"]["rules"].as_array().unwrap();
+        let results = run["results"].as_array().unwrap();
+        assert_eq!(results.len(), 2);
+        for result in results {
+            assert!(rules.iter().any(|rule| rule["id"] == result["ruleId"]));
+        }
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"]["artifactLocation"]["uri"],
+            "normal/Cargo.toml"
+        );
+        assert_eq!(
+            results[0]["locations"][0]["physicalLocation"]["region"]["startLine"],
+            7
+        );
+
+        let output = check(&dst, "serde", "1.0.123", "1.0.124", &options).unwrap();
+        assert!(output.up_to_date);
+        assert!(output.out_of_date_manifests.is_empty());
+
+        // in a repository, the paths are relative to its root rather than to the workspace
+        let repository = tempfile::tempdir().unwrap().into_path();
+        let workspace = repository.join("ws");
+        fs::rename(&dst, &workspace).unwrap();
+        let status = Command::new("git")
+            .current_dir(&repository)
+            .args(["init", "--quiet"])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let output = check(&workspace, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        let toplevel = git::toplevel(&workspace).unwrap();
+        let annotation = github_annotation(
+            &toplevel,
+            &output.occurrences[0],
+            "serde",
+            "1.0.122",
+            "1.0.123",
+        );
+        assert_eq!(
+            annotation,
+            "::warning file=ws/normal/Cargo.toml,line=7::serde is out of date (1.0.122, latest 1.0.123)"
+        );
+    }
+
+    #[test]
+    fn test_workspace_inheritance() {
+        let dst = copy_fixture("workspace_inheritance");
+        let options = Options::default();
+
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![dst.join("Cargo.toml"), dst.join("overrides/Cargo.toml")]
+        );
+        assert_eq!(
+            output.inherited_manifests,
+            vec![dst.join("inherits/Cargo.toml")]
+        );
+        assert_eq!(
+            output.overridden_manifests,
+            vec![dst.join("overrides/Cargo.toml")]
+        );
+
+        // the whole workspace is now consistent
+        let root = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(root.contains(r#"serde = "1.0.123""#));
+        let overrides = fs::read_to_string(dst.join("overrides/Cargo.toml")).unwrap();
+        assert!(overrides.contains(r#"serde = { version = "1.0.123", features = ["derive"] }"#));
+        let inherits = fs::read_to_string(dst.join("inherits/Cargo.toml")).unwrap();
+        assert!(inherits.contains("serde = { workspace = true }"));
+    }
+
+    #[test]
+    fn test_workspace_scope() {
+        let dst = copy_fixture("workspace_scope");
+        let options = Options {
+            workspace_scope: true,
+            ..Options::default()
+        };
+
+        // the member declaring its own version is left alone, and reported as diverging
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+        assert_eq!(output.inherited_manifests, vec![dst.join("lib/Cargo.toml")]);
+        assert_eq!(output.diverging_manifests, vec![dst.join("app/Cargo.toml")]);
+        let root = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(root.contains(r#"serde = "1.0.123""#));
+        let app = fs::read_to_string(dst.join("app/Cargo.toml")).unwrap();
+        assert!(app.contains(r#"serde = "1.0.122""#));
+    }
+
+    #[test]
+    fn test_workspace_divergence() {
+        let dst = copy_fixture("workspace_divergence");
+        let options = Options::default();
+
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![dst.join("Cargo.toml"), dst.join("overrides/Cargo.toml")]
+        );
+        assert_eq!(
+            output.overridden_manifests,
+            vec![dst.join("overrides/Cargo.toml")]
+        );
+
+        // the member stuck on an older version still diverges from the workspace
+        assert_eq!(
+            output.diverging_manifests,
+            vec![dst.join("diverges/Cargo.toml")]
+        );
+        let diverges = fs::read_to_string(dst.join("diverges/Cargo.toml")).unwrap();
+        assert!(diverges.contains(r#"serde = { version = "1.0.100" }"#));
+    }
+
+    #[test]
+    fn test_aliases() {
+        let dst = copy_fixture("aliases");
+        let options = Options {
+            discover_glob: Some("Cargo.toml".to_string()),
+            ..Options::default()
+        };
+
+        // every declaration of the package is updated, whatever its key
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        let aliases: Vec<_> = output
+            .updated_aliases
+            .iter()
+            .map(|alias| alias.key.as_str())
+            .collect();
+        assert_eq!(aliases, vec!["serde1", "serde_dev"]);
+
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"serde = "1.0.123""#));
+        assert!(manifest.contains(r#"serde1 = { package = "serde", version = "1.0.123" }"#));
+        assert!(manifest.contains(r#"serde_dev = { package = "serde", version = "1.0.123" }"#));
+        assert!(manifest.contains(r#"serde_old = { package = "serde", version = "1.0.100" }"#));
+    }
+
+    #[test]
+    fn test_alias_keys() {
+        let dst = copy_fixture("alias_keys");
+        let manifest = dst.join("Cargo.toml");
+
+        // the alias is found through its package...
+        let options = Options::default();
+        let output = update_manifests(&dst, "serde_json", "1.0.60", "1.0.61", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![manifest.clone()]);
+        let content = fs::read_to_string(&manifest).unwrap();
+        assert!(content.contains(r#"my_json = { package = "serde_json", version = "1.0.61" }"#));
+
+        // ...and through its key, which cargo knows by the name of the package
+        let output = update_manifests(&dst, "my_json", "1.0.61", "1.0.62", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![manifest.clone()]);
+        let content = fs::read_to_string(&manifest).unwrap();
+        assert!(content.contains(r#"my_json = { package = "serde_json", version = "1.0.62" }"#));
+        assert_eq!(
+            resolve_package_name(&dst, "my_json", &options).unwrap(),
+            "serde_json"
+        );
+        assert_eq!(
+            resolve_package_name(&dst, "serde_json", &options).unwrap(),
+            "serde_json"
+        );
+
+        // unless only the package names are matched
+        let options = Options {
+            by_package_name: true,
+            ..Options::default()
+        };
+        let output = update_manifests(&dst, "my_json", "1.0.62", "1.0.63", &options).unwrap();
+        assert!(output.updated_manifests.is_empty());
+        let output = update_manifests(&dst, "serde_json", "1.0.62", "1.0.63", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![manifest]);
+    }
+
+    #[test]
+    fn test_renovate_format() {
+        let dst = copy_fixture("dep_kinds");
+        let options = Options {
+            discover_glob: Some("*/Cargo.toml".to_string()),
+            ..Options::default()
+        };
+
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        let output = RenovateOutput::new(&dst, &output, "serde", "1.0.123");
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(
+            json["updates"][0],
+            serde_json::json!({
+                "depName": "serde",
+                "currentValue": "1.0.122",
+                "newValue": "1.0.123",
+                "packageFile": "dev/Cargo.toml",
+                "depType": "dev-dependencies",
+                "datasource": "crate",
+            })
+        );
+
+        // the paths from cargo metadata are relative to a root given with a relative path
+        let dst = copy_fixture("dep_kinds");
+        let root_dir = resolve_root_dir(relative_manifest_path(&dst).to_str(), None);
+        assert_eq!(root_dir, dst);
+        let output = update_manifests(
+            &root_dir,
+            "serde",
+            "1.0.122",
+            "1.0.123",
+            &Options::default(),
+        )
+        .unwrap();
+        let output = RenovateOutput::new(&root_dir, &output, "serde", "1.0.123");
+        let json = serde_json::to_value(&output).unwrap();
+        assert_eq!(json["updates"][0]["packageFile"], "normal/Cargo.toml");
+        assert_eq!(json["updates"][1]["packageFile"], "dev/Cargo.toml");
+
+        // the other modes don't have a renovate output
+        let args = ["cargo-update-dep", "--format", "renovate"];
+        for mode in [
+            &["-p", "serde", "-v", "1.0.122", "-n", "1.0.123", "--check"][..],
+            &["--set", "serde=1.0.200"],
+            &["--git-ref", "v1.0.0", "-p", "serde"],
+        ] {
+            let matches = cli().get_matches_from(args.iter().chain(mode));
+            let err = ensure_format_supported(&matches).unwrap_err();
+            assert!(err.contains("--format renovate"), "{}", err);
+        }
+        let matches = cli().get_matches_from(
+            args.iter()
+                .chain(&["-p", "serde", "-v", "1.0.122", "-n", "1.0.123"]),
+        );
+        assert!(ensure_format_supported(&matches).is_ok());
+    }
+
+    #[test]
+    fn test_preserve_version_prefix() {
+        assert_eq!(with_prefix_of("1.2.3", "1.3.0"), "1.3.0");
+        assert_eq!(with_prefix_of("1.2.3", "^1.3.0"), "1.3.0");
+        assert_eq!(with_prefix_of("^1.2.3", "1.3.0"), "^1.3.0");
+        assert_eq!(with_prefix_of("~1.2", "= 1.3.0"), "~1.3.0");
+        assert_eq!(with_prefix_of(">=1.2", "1.3"), ">=1.3");
+        assert_eq!(with_prefix_of(">=1.2, <2", "1.3"), "1.3");
+
+        let path = Path::new("Cargo.toml");
+        let content = r#"[dependencies]
+bare = "1.2.3"
+caret = "^1.2.3"
+tilde = { version = "~1.2.3" }
+"#;
+        let options = Options {
+            preserve_version_prefix: true,
+            any_version: true,
+            ..Options::default()
+        };
+        let mut result = content.to_string();
+        for package in &["bare", "caret", "tilde"] {
+            result = update_manifest(path, &result, package, "*", "^1.3.0", &options).unwrap();
+        }
+        assert_eq!(
+            result,
+            r#"[dependencies]
+bare = "1.3.0"
+caret = "^1.3.0"
+tilde = { version = "~1.3.0" }
+"#
+        );
+    }
+
+    #[test]
+    fn test_inline_tables() {
+        let dst = copy_fixture("inline_tables");
+        let options = Options::default();
+
+        // only the versions change, trailing commas and spacing are kept
+        update_manifests(&dst, "foo", "0.1.1", "0.2.0", &options).unwrap();
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"foo = {version="0.2.0",   features = ["std"], }"#));
+        assert!(manifest.contains(r#"foo = { version = "0.2.0" ,default-features = false,}"#));
+
+        let options = Options {
+            normalize_inline_tables: true,
+            ..Options::default()
+        };
+        update_manifests(&dst, "foo", "0.2.0", "0.3.0", &options).unwrap();
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"foo = { version = "0.3.0", features = ["std"] }"#));
+        assert!(manifest.contains(r#"foo = { version = "0.3.0", default-features = false }"#));
+        // other declarations are left alone
+        assert!(manifest.contains(r#"bar = {version = "0.1.1",}"#));
+    }
+
+    #[test]
+    fn test_git_dependency() {
+        let dst = copy_fixture("git_dependency");
+        let options = Options {
+            discover_glob: Some("*/Cargo.toml".to_string()),
+            ..Options::default()
+        };
+
+        // only the declaration with a version can be bumped
+        let output = update_manifests(&dst, "foo", "0.1.1", "0.2.0", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![dst.join("versioned/Cargo.toml")]
+        );
+        assert_eq!(
+            output.unversioned_git_manifests,
+            vec![dst.join("unversioned/Cargo.toml")]
+        );
+    }
+
+    #[test]
+    fn test_diff_scope() {
+        let dst = copy_fixture("diff_scope");
+        let options = Options {
+            discover_glob: Some("Cargo.toml".to_string()),
+            ..Options::default()
+        };
+
+        let diff_of = |scope| {
+            let diffs = diff(&dst, "foo", "0.1.1", "0.2.0", &options, scope, 0).unwrap();
+            render_diff(&diffs, false)
+        };
+        assert_eq!(
+            diff_of("table"),
+            r#"--- Cargo.toml
++++ Cargo.toml
+[dependencies]
+-foo = { version = "0.1.1", features = [
+-    "std",
+-] }
++foo = { version = "0.2.0", features = [
++    "std",
++] }
+"#
+        );
+        assert_eq!(
+            diff_of("line"),
+            r#"--- Cargo.toml
++++ Cargo.toml
+-foo = { version = "0.1.1", features = [
++foo = { version = "0.2.0", features = [
+"#
+        );
+        assert_eq!(diff_of("file").lines().count(), 2 + 10 + 1);
+
+        // nothing was modified
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"version = "0.1.1""#));
+    }
+
+    #[test]
+    fn test_diff_hunks() {
+        let dst = copy_fixture("diff_hunks");
+        let options = Options {
+            discover_glob: Some("Cargo.toml".to_string()),
+            ..Options::default()
+        };
+        let diff_of = |scope, context, color| {
+            let diffs = diff(&dst, "foo", "0.1.1", "0.2.0", &options, scope, context).unwrap();
+            render_diff(&diffs, color)
+        };
+
+        // the changes of both tables are grouped under the header of the file
+        assert_eq!(
+            diff_of("table", 0, false),
+            r#"--- Cargo.toml
++++ Cargo.toml
+[dependencies]
+-foo = "0.1.1"
++foo = "0.2.0"
+
+[dev-dependencies]
+-foo = { version = "0.1.1", features = ["std"] }
++foo = { version = "0.2.0", features = ["std"] }
+"#
+        );
+        assert_eq!(
+            diff_of("line", 1, false),
+            r#"--- Cargo.toml
++++ Cargo.toml
+ [dependencies]
+-foo = "0.1.1"
++foo = "0.2.0"
+ bar = "0.1.1"
+
+ baz = "0.1.1"
+-foo = { version = "0.1.1", features = ["std"] }
++foo = { version = "0.2.0", features = ["std"] }
+"#
+        );
+
+        // hunks overlapping with their context are merged
+        assert_eq!(diff_of("line", 2, false).matches("\n\n").count(), 0);
+
+        assert!(diff_of("line", 0, true).contains("\x1b[31m-foo = \"0.1.1\"\x1b[0m\n"));
+    }
+
+    #[test]
+    fn test_diff_normalized() {
+        let dst = copy_fixture("multiline_inline_table");
+        let options = Options {
+            discover_glob: Some("Cargo.toml".to_string()),
+            normalize_inline_tables: true,
+            ..Options::default()
+        };
+        let diff_of = |scope, context| {
+            let diffs = diff(&dst, "foo", "0.1.1", "0.2.0", &options, scope, context).unwrap();
+            render_diff(&diffs, false)
+        };
+
+        // the normalized table spans fewer lines than the original one
+        assert_eq!(
+            diff_of("table", 0),
+            r#"--- Cargo.toml
++++ Cargo.toml
+[dependencies]
+-foo = {
+-    version = "0.1.1",
+-    features = ["std"],
+-}
++foo = { version = "0.2.0", features = ["std"] }
+"#
+        );
+        assert_eq!(
+            diff_of("line", 1),
+            r#"--- Cargo.toml
++++ Cargo.toml
+ [dependencies]
+-foo = {
+-    version = "0.1.1",
+-    features = ["std"],
+-}
++foo = { version = "0.2.0", features = ["std"] }
+ bar = "0.1.1"
+"#
+        );
+    }
+
+    #[test]
+    fn test_patch() {
+        let apply = |fixture, package, version, new_version, options: &Options| {
+            let dst = copy_fixture(fixture);
+            let expected = copy_fixture(fixture);
+            let patch = patch(&dst, package, version, new_version, options).unwrap();
+            assert!(patch.starts_with("diff --git a/"));
+
+            // the patch applies to the workspace, like the update itself
+            fs::write(dst.join("update.patch"), &patch).unwrap();
+            let status = Command::new("git")
+                .current_dir(&dst)
+                .args(["apply", "update.patch"])
+                .status()
+                .unwrap();
+            assert!(status.success(), "git apply failed on:\n{}", patch);
+            let output =
+                update_manifests(&expected, package, version, new_version, options).unwrap();
+            for manifest in output.updated_manifests {
+                let path = manifest.strip_prefix(&expected).unwrap();
+                assert_eq!(
+                    fs::read_to_string(dst.join(path)).unwrap(),
+                    fs::read_to_string(&manifest).unwrap()
+                );
             }
+            patch
+        };
+
+        let patch = apply(
+            "dep_kinds",
+            "serde",
+            "1.0.122",
+            "1.0.123",
+            &Options::default(),
+        );
+        assert!(patch.contains("--- a/normal/Cargo.toml\n+++ b/normal/Cargo.toml\n@@ -4,"));
+
+        // without a newline at the end of the manifest
+        let options = Options {
+            discover_glob: Some("Cargo.toml".to_string()),
+            ..Options::default()
+        };
+        let patch = apply("no_trailing_newline", "foo", "0.1.1", "0.2.0", &options);
+        assert!(patch.ends_with("+foo = \"0.2.0\"\n\\ No newline at end of file\n"));
+
+        // normalized inline tables can span fewer lines
+        let options = Options {
+            discover_glob: Some("Cargo.toml".to_string()),
+            normalize_inline_tables: true,
+            ..Options::default()
+        };
+        let patch = apply("multiline_inline_table", "foo", "0.1.1", "0.2.0", &options);
+        assert!(patch.contains("@@ -4,8 +4,5 @@\n"));
+    }
+
+    #[test]
+    fn test_root_dir() {
+        let manifest_dir = || Some(OsString::from("/from/env"));
+        assert_eq!(
+            resolve_root_dir(Some("/from/arg/Cargo.toml"), manifest_dir()),
+            Path::new("/from/arg")
+        );
+        assert_eq!(
+            resolve_root_dir(None, manifest_dir()),
+            Path::new("/from/env")
+        );
+        assert_eq!(
+            resolve_root_dir(None, None),
+            std::env::current_dir().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_no_op_update() {
+        let dst = copy_fixture("dep_kinds");
+        let options = Options {
+            discover_glob: Some("*/Cargo.toml".to_string()),
+            ..Options::default()
+        };
+
+        // the requirements are rewritten with the same bytes, so nothing is reported
+        let content = fs::read_to_string(dst.join("dev/Cargo.toml")).unwrap();
+        let path = dst.join("dev/Cargo.toml");
+        assert!(
+            update_manifest(&path, &content, "serde", "1.0.122", "1.0.122", &options).is_none()
+        );
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.122", &options).unwrap();
+        assert!(output.updated_manifests.is_empty());
+        assert!(output.updated_declarations.is_empty());
+    }
+
+    /// The keys always present in the JSON of `Output`. Scripts rely on them, so they stay.
+    const STABLE_OUTPUT_KEYS: &[&str] = &[
+        "updated_manifests",
+        "inherited_manifests",
+        "overridden_manifests",
+        "diverging_manifests",
+        "updated_aliases",
+        "unversioned_git_manifests",
+        "skipped_manifests",
+        "lock_update",
+    ];
+
+    #[test]
+    fn test_output_keys() {
+        let json = serde_json::to_value(Output::default()).unwrap();
+        let keys: Vec<_> = json.as_object().unwrap().keys().cloned().collect();
+
+        // the stable keys are all there, and new keys must be added to them
+        let mut expected = STABLE_OUTPUT_KEYS.to_vec();
+        expected.sort_unstable();
+        assert_eq!(keys, expected);
+
+        // the key of the first version, parsed by the oldest scripts
+        assert!(json["updated_manifests"].is_array());
+
+        // the optional keys only show up when relevant
+        let output = Output {
+            relaxed_versions: vec!["0.1.0".to_string()],
+            git: Some(GitOutput {
+                branch: None,
+                commit: None,
+                pushed: false,
+            }),
+            ..Output::default()
+        };
+        let json = serde_json::to_value(output).unwrap();
+        for key in STABLE_OUTPUT_KEYS
+            .iter()
+            .chain(&["relaxed_versions", "git"])
+        {
+            assert!(json.get(key).is_some(), "missing {}", key);
         }
+    }
+
+    #[test]
+    fn test_journal() {
+        let dst = copy_fixture("workspace_path");
+        let journal_path = dst.join(JOURNAL_FILE);
+        let options = Options {
+            no_lock_check: true,
+            no_create_lock: true,
+            journal: Some(journal_path.clone()),
+            ..Options::default()
+        };
+        let original = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
 
-        //
-        lines.push(line);
+        run(&dst, "foo", "0.1.1", "0.2.0", &options).unwrap();
+        let journal: Journal =
+            serde_json::from_str(&fs::read_to_string(journal_path).unwrap()).unwrap();
+        assert_eq!(
+            (journal.package.as_str(), journal.version.as_str()),
+            ("foo", "0.1.1")
+        );
+        assert_eq!(journal.new_version, "0.2.0");
+        assert_eq!(journal.files.len(), 1);
+        assert_eq!(journal.files[0].path, dst.join("Cargo.toml"));
+        assert_eq!(journal.files[0].original, original);
     }
 
-    // if the file needs change, update it
-    if updated {
-        let mut file = File::create(manifest_path).expect("Failed to update manifest file");
-        file.write_all(lines.join("\n").as_bytes())
-            .expect("Failed to write to file");
-        file.write_all(b"\n").expect("Failed to write to file");
+    /// Returns a registry serving the index files of the given crates, and its URL.
+    #[cfg(feature = "network")]
+    fn mock_registry(index: &[(&str, &[&str])]) -> (tempfile::TempDir, String) {
+        let registry = tempfile::tempdir().unwrap();
+        for (name, versions) in index {
+            let index_file = registry.path().join(registry::index_path(name));
+            fs::create_dir_all(index_file.parent().unwrap()).unwrap();
+            let lines: Vec<_> = versions
+                .iter()
+                .map(|version| {
+                    format!(
+                        r#"{{"name":"{}","vers":"{}","yanked":false}}"#,
+                        name, version
+                    )
+                })
+                .collect();
+            fs::write(index_file, lines.join("\n")).unwrap();
+        }
+        let index_url = format!("file://{}", registry.path().display());
+        (registry, index_url)
     }
 
-    //
-    updated
-}
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_update_all_compatible() {
+        let dst = copy_fixture("all_compatible");
+        let options = Options {
+            no_create_lock: true,
+            ..Options::default()
+        };
 
-fn update_cargo_lock(root_dir: &Path, package: &str, version: &str) {
-    let pkgid = format!("{}:{}", package, version);
-    // run `cargo metadata`
-    let _output = Command::new("cargo")
-        .current_dir(root_dir)
-        .args(&["update", "-p"])
-        .arg(pkgid)
-        .output()
-        .expect("failed to execute process");
-    //    assert!(output.status.success());
-    // this last command might fail if the user is running something in parallel to update the Cargo.lock
-}
+        let (_registry, index_url) = mock_registry(&[
+            ("rand", &["0.7.2", "0.7.3", "0.8.3"]),
+            ("serde", &["1.0.100", "1.0.123", "2.0.0-alpha.1"]),
+            ("exact", &["1.0.0", "1.0.1"]),
+        ]);
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs;
+        let output = update_all_compatible(&dst, None, &index_url, &options).unwrap();
+        let transitions: Vec<_> = output
+            .transitions
+            .iter()
+            .map(|t| (t.package.as_str(), t.from.as_str(), t.to.as_str()))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![("rand", "0.7", "0.7.3"), ("serde", "^1.0.100", "^1.0.123")]
+        );
+
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"rand = "0.7.3""#));
+        assert!(manifest.contains(r#"serde = { version = "^1.0.123", features = ["derive"] }"#));
+        assert!(manifest.contains(r#"exact = "=1.0.0""#));
+
+        // only the allowed dependencies are looked up
+        let dst = copy_fixture("all_compatible");
+        let options = Options {
+            allowed_dependencies: vec!["ser*".to_string()],
+            ..options
+        };
+        let output = update_all_compatible(&dst, None, &index_url, &options).unwrap();
+        assert_eq!(output.transitions.len(), 1);
+        assert_eq!(output.transitions[0].package, "serde");
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"rand = "0.7""#));
+    }
 
     #[test]
-    fn test_everything() {
-        // first copy our Cargo.toml so we don't rewrite it
-        let mut src = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-        src.push("resources/test");
-        let dst = tempfile::tempdir().unwrap().into_path();
-        fs::copy(
-            src.as_path().join("Cargo.toml"),
-            dst.as_path().join("Cargo.toml"),
+    #[cfg(feature = "network")]
+    fn test_all_direct_deps() {
+        let dst = copy_fixture("direct_deps");
+        let options = Options {
+            no_create_lock: true,
+            ..Options::default()
+        };
+        let (_registry, index_url) = mock_registry(&[
+            ("rand", &["0.7.3"]),
+            ("serde", &["1.0.123"]),
+            ("tester", &["0.1.5"]),
+            ("old", &["0.1.5"]),
+        ]);
+
+        // dev-dependencies are left out, and the ignored member is skipped
+        let output = update_direct_dependencies(&dst, None, &index_url, &options).unwrap();
+        let outcomes: Vec<_> = output
+            .outcomes
+            .iter()
+            .map(|outcome| (outcome.package.as_str(), &outcome.outcome))
+            .collect();
+        assert_eq!(
+            outcomes,
+            vec![
+                ("old", &Outcome::Skipped),
+                ("rand", &Outcome::Updated),
+                ("serde", &Outcome::UpToDate),
+                ("unknown", &Outcome::Unavailable),
+            ]
+        );
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"rand = "0.7.3""#));
+        assert!(manifest.contains(r#"tester = "0.1""#));
+        let legacy = fs::read_to_string(dst.join("legacy/Cargo.toml")).unwrap();
+        assert!(legacy.contains(r#"old = "0.1""#));
+
+        // the dependency name pattern and kinds narrow the set down
+        let options = Options {
+            dep_kinds: vec!["dev".to_string()],
+            ..options
+        };
+        let output = update_direct_dependencies(&dst, Some("t*"), &index_url, &options).unwrap();
+        assert_eq!(output.outcomes.len(), 1);
+        assert_eq!(output.outcomes[0].outcome, Outcome::Updated);
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"tester = "0.1.5""#));
+    }
+
+    #[test]
+    fn test_git_ref() {
+        let dst = copy_fixture("git_workspace");
+        let options = Options {
+            no_create_lock: true,
+            ..Options::default()
+        };
+
+        let output = update_git_refs(&dst, "foo", "v0.2.0", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+        assert_eq!(output.inherited_manifests, vec![dst.join("app/Cargo.toml")]);
+
+        let root = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(
+            root.contains(r#"foo = { git = "https://github.com/example/foo", tag = "v0.2.0" }"#)
+        );
+        let app = fs::read_to_string(dst.join("app/Cargo.toml")).unwrap();
+        assert!(app.contains("foo.workspace = true"));
+    }
+
+    #[test]
+    fn test_relaxed_match() {
+        let dst = copy_fixture("workspace_path");
+        let options = Options {
+            no_create_lock: true,
+            ..Options::default()
+        };
+        assert_eq!(
+            declared_versions(&dst, "foo", &options).unwrap(),
+            vec!["0.1.1"]
+        );
+
+        // strict by default
+        let strict = Options {
+            no_lock_check: true,
+            ..options.clone()
+        };
+        let output = run(&dst, "foo", "0.1.0", "0.2.0", &strict).unwrap();
+        assert!(output.updated_manifests.is_empty());
+
+        let relaxed = Options {
+            any_version: true,
+            ..options
+        };
+        let output = run(&dst, "foo", "0.1.0", "0.2.0", &relaxed).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+        assert_eq!(output.relaxed_versions, vec!["0.1.1"]);
+    }
+
+    #[test]
+    fn test_pr_body() {
+        let dst = copy_fixture("dep_kinds");
+        let options = Options {
+            discover_glob: Some("*/Cargo.toml".to_string()),
+            ..Options::default()
+        };
+
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        let body = render_pr_body(
+            PR_BODY_TEMPLATE,
+            &dst,
+            &output,
+            "serde",
+            "1.0.122",
+            "1.0.123",
+        );
+        assert_eq!(
+            body,
+            "Bump `serde` from 1.0.122 to 1.0.123
+
+- `dev/Cargo.toml` (`dev-dependencies`): 1.0.122 → 1.0.123
+- `normal/Cargo.toml` (`dependencies`): 1.0.122 → 1.0.123
+
+See [serde on crates.io](https://crates.io/crates/serde) for the changes of this version.
+"
+        );
+
+        let body = render_pr_body(
+            "{package}: {new_version}",
+            &dst,
+            &output,
+            "serde",
+            "1.0.122",
+            "1.0.123",
+        );
+        assert_eq!(body, "serde: 1.0.123");
+    }
+
+    #[test]
+    fn test_relative_root() {
+        let dst = copy_fixture("dep_kinds");
+        let root_dir = resolve_root_dir(relative_manifest_path(&dst).to_str(), None);
+        let options = Options::default();
+        let (package, version, new_version) = ("serde", "1.0.122", "1.0.123");
+        let expected = ["normal/Cargo.toml", "dev/Cargo.toml"];
+
+        // every renderer reports the manifests from cargo metadata relative to the root
+        let diffs = diff(
+            &root_dir,
+            package,
+            version,
+            new_version,
+            &options,
+            "line",
+            0,
         )
         .unwrap();
-        fs::create_dir(dst.as_path().join("src")).unwrap();
-        fs::File::create(dst.as_path().join("src/lib.rs")).unwrap();
+        let paths: Vec<_> = diffs.iter().map(|diff| diff.path.clone()).collect();
+        assert_eq!(paths, expected.map(PathBuf::from));
 
-        // run on that Cargo.toml
-        run(&dst, "serde", "1.0.122", "1.0.123");
-        run(&dst, "serde_json", "1.0.60", "1.0.61");
-        run(&dst, "regex", "0.1.77", "1.4.3");
-        run(&dst, "lazy_static", "0.2.11", "1.4.0");
+        let patch = patch(&root_dir, package, version, new_version, &options).unwrap();
+        assert!(patch.starts_with("diff --git a/normal/Cargo.toml b/normal/Cargo.toml\n"));
 
-        // check that it worked
-        let result = fs::read_to_string(dst.as_path().join("Cargo.toml")).unwrap();
-        let expected = fs::read_to_string(src.as_path().join("Cargo.toml.new")).unwrap();
+        let output = check(&root_dir, package, version, new_version, &options).unwrap();
+        let report = sarif_report(
+            &root_dir,
+            &output.occurrences,
+            package,
+            version,
+            new_version,
+        );
+        let uris: Vec<_> = report["runs"][0]["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|result| &result["locations"][0]["physicalLocation"]["artifactLocation"]["uri"])
+            .collect();
+        assert_eq!(uris, expected);
 
-        assert!(result == expected);
+        let output = update_manifests(&root_dir, package, version, new_version, &options).unwrap();
+        let body = render_pr_body(
+            "{changes}",
+            &root_dir,
+            &output,
+            package,
+            version,
+            new_version,
+        );
+        assert!(
+            body.contains("- `normal/Cargo.toml` (`dependencies`)"),
+            "{}",
+            body
+        );
+        assert!(
+            body.contains("- `dev/Cargo.toml` (`dev-dependencies`)"),
+            "{}",
+            body
+        );
+    }
+
+    #[test]
+    fn test_lock_workspace() {
+        let dst = copy_fixture("workspace_path");
+        let metadata = get_metadata(&dst).unwrap();
+
+        // without a target directory, the lock file is next to the Cargo.lock
+        let lock = lock_workspace(&dst, Some(&metadata), false).unwrap();
+        assert!(dst.join(".cargo-update-dep.lock").exists());
+        assert!(!dst.join("target").exists());
+        drop(lock);
+
+        fs::create_dir(dst.join("target")).unwrap();
+        let lock = lock_workspace(&dst, Some(&metadata), false).unwrap();
+        assert!(dst.join("target/cargo-update-dep.lock").exists());
+        let err = lock_workspace(&dst, Some(&metadata), false).err().unwrap();
+        assert!(err.contains("--no-wait"));
+
+        // released when closed
+        drop(lock);
+        assert!(lock_workspace(&dst, Some(&metadata), false).is_ok());
+
+        // cargo metadata failed
+        let dst = copy_fixture("broken_member");
+        assert!(lock_workspace(&dst, None, false).is_ok());
+        assert!(dst.join(".cargo-update-dep.lock").exists());
+        assert!(!dst.join("target").exists());
+    }
+
+    #[test]
+    #[cfg(feature = "watch")]
+    fn test_watch() {
+        // the subcommand takes the arguments of an update
+        let matches = cli().get_matches_from(vec![
+            "cargo-update-dep",
+            "watch",
+            "-p",
+            "serde",
+            "-v",
+            "1.0",
+            "-n",
+            "2.0",
+        ]);
+        let matches = watch_matches(matches.subcommand_matches("watch").unwrap());
+        assert_eq!(matches.value_of("dependency_name"), Some("serde"));
+        assert!(ensure_watch_supported(&matches).is_ok());
+        let matches = cli().get_matches_from(vec![
+            "cargo-update-dep",
+            "watch",
+            "-p",
+            "serde",
+            "-v",
+            "1.0",
+            "-n",
+            "2.0",
+            "--check",
+        ]);
+        let matches = watch_matches(matches.subcommand_matches("watch").unwrap());
+        let err = ensure_watch_supported(&matches).unwrap_err();
+        assert!(err.contains("--check"));
+
+        // the first run moved serde to 2.0.0 in the Cargo.lock, then the manifest was reverted
+        let dst = copy_fixture("pin_to_lock");
+        let options = Options::default();
+        let lock = fs::read_to_string(dst.join("Cargo.lock")).unwrap();
+        fs::write(
+            dst.join("Cargo.lock"),
+            lock.replace(
+                "name = \"serde\"\nversion = \"1.0.123\"",
+                "name = \"serde\"\nversion = \"2.0.0\"",
+            ),
+        )
+        .unwrap();
+        let err = run(&dst, "serde", "1.0", "2.0", &options).err().unwrap();
+        assert!(err.contains("does not contain serde 1.0"), "{}", err);
+
+        // the second run applies the update again
+        let output = rerun(&dst, "serde", "1.0", "2.0", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"serde = "2.0""#));
+    }
+
+    #[test]
+    fn test_lock_check() {
+        let dst = copy_fixture("lock_check");
+        let options = Options::default();
+
+        // the Cargo.lock still resolves serde to 0.9.15
+        let err = run(&dst, "serde", "1.0.122", "1.0.124", &options).err();
+        assert!(err.unwrap().contains("does not contain serde 1.0.122"));
+
+        // nothing was modified
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"serde = "1.0.122""#));
+
+        assert_eq!(
+            check_lock(&dst, "serde", "0.9.15"),
+            Ok(Some("0.9.15".to_string()))
+        );
+
+        // partial versions match the locked one
+        assert_eq!(
+            check_lock(&dst, "serde", "0.9"),
+            Ok(Some("0.9.15".to_string()))
+        );
+
+        // without a lockfile there's nothing to verify
+        fs::remove_file(dst.join("Cargo.lock")).unwrap();
+        assert_eq!(check_lock(&dst, "serde", "1.0.122"), Ok(None));
+    }
+
+    #[test]
+    fn test_discover_glob() {
+        let dst = copy_fixture("discover_glob");
+        let options = Options {
+            discover_glob: Some("crates/**/Cargo.toml".to_string()),
+            ..Options::default()
+        };
+
+        let output = run(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![
+                dst.join("crates/a/Cargo.toml"),
+                dst.join("crates/nested/b/Cargo.toml")
+            ]
+        );
+        assert!(output.lock_update.is_none());
+
+        // manifests outside of the pattern are left alone
+        let other = fs::read_to_string(dst.join("other/Cargo.toml")).unwrap();
+        assert!(other.contains(r#"serde = "1.0.122""#));
+    }
+
+    #[test]
+    fn test_read_only_modes() {
+        // the modes that edit the manifests on their own can't be previewed or checked
+        for mode in [
+            &["--set", "serde=1.0.123"][..],
+            &["--git-ref", "v1.0.0", "-p", "serde"],
+            &["--pin-to-lock", "-p", "serde"],
+            #[cfg(feature = "network")]
+            &["--update-all-compatible"],
+            #[cfg(feature = "network")]
+            &["--all-direct-deps"],
+        ] {
+            for read_only in [
+                &["--check"][..],
+                &["--diff-scope", "line"],
+                &["--emit", "patch"],
+            ] {
+                let args = ["cargo-update-dep"].iter().chain(mode).chain(read_only);
+                let err = cli().get_matches_from_safe(args).err().unwrap();
+                assert_eq!(err.kind, clap::ErrorKind::ArgumentConflict);
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_dependency_modes() {
+        // the other modes are neither committed, journaled nor counted
+        let modes = [
+            &["--set", "serde=1.0.200"][..],
+            &["--git-ref", "v1.0.0", "-p", "serde"],
+            &["--pin-to-lock", "-p", "serde"],
+            &[
+                "-p",
+                "serde",
+                "-v",
+                "1.0.122",
+                "-n",
+                "1.0.123",
+                "--diff-scope",
+                "line",
+            ],
+            #[cfg(feature = "network")]
+            &["--update-all-compatible"],
+            #[cfg(feature = "network")]
+            &["--all-direct-deps"],
+        ];
+        for flag in ["--commit", "--journal", "--count"] {
+            for mode in modes {
+                let flags = [flag, "--yes"];
+                let args = ["cargo-update-dep"].iter().chain(mode).chain(&flags);
+                let err = cli().get_matches_from_safe(args).err().unwrap();
+                assert_eq!(err.kind, clap::ErrorKind::ArgumentConflict, "{}", flag);
+            }
+        }
+
+        // glob patterns are only known to be such once parsed
+        for flags in [
+            &["--commit"][..],
+            &["--journal"],
+            &["--relaxed-match"],
+            &["--pr-body", "body.md"],
+            &["--explain-resolution"],
+            &["--format", "renovate"],
+            #[cfg(feature = "network")]
+            &["--verify-published"],
+        ] {
+            let args = [
+                "cargo-update-dep",
+                "-p",
+                "serde*",
+                "-v",
+                "1.0.122",
+                "-n",
+                "1.0.123",
+            ];
+            let matches = cli().get_matches_from(args.iter().chain(flags));
+            let err = ensure_glob_supported(&matches).unwrap_err();
+            assert!(err.starts_with(flags[0]), "{}", err);
+        }
+
+        // the other flags are supported
+        let matches = cli().get_matches_from(vec![
+            "cargo-update-dep",
+            "-p",
+            "serde*",
+            "-v",
+            "1.0.122",
+            "-n",
+            "1.0.123",
+            "--count",
+        ]);
+        assert!(ensure_glob_supported(&matches).is_ok());
+    }
+
+    #[test]
+    fn test_print_config() {
+        let matches = cli().get_matches_from(vec![
+            "cargo-update-dep",
+            "-p",
+            "serde",
+            "-v",
+            "1.0.122",
+            "-n",
+            "1.0.123",
+            "-m",
+            "/tmp/Cargo.toml",
+            "--dep-kind",
+            "dev",
+            "--ignore-case",
+            // accepted (and ignored) without the network feature too
+            "--index-cache",
+            "/tmp/index",
+            "--index-cache-ttl",
+            "60",
+        ]);
+        let root_dir = get_root_dir(&matches);
+        let options = Options::from_matches(&matches, None);
+        let config = Config {
+            root_dir: &root_dir,
+            mode: get_mode(&matches),
+            package: Some("serde"),
+            version: Some("1.0.122"),
+            new_version: Some("1.0.123"),
+            set: vec![],
+            options: &options,
+        };
+
+        let config = serde_json::to_value(&config).unwrap();
+        assert_eq!(config["root_dir"], "/tmp");
+        assert_eq!(config["mode"], "update");
+        assert_eq!(config["dep_kinds"], serde_json::json!(["dev"]));
+        assert_eq!(config["ignore_case"], true);
+        assert_eq!(config["no_lock_check"], false);
+        assert_eq!(config["discover_glob"], serde_json::Value::Null);
+        assert_eq!(config["index_cache"], "/tmp/index");
+        assert_eq!(config["index_cache_ttl"], 60);
+    }
+
+    #[test]
+    fn test_config_precedence() {
+        let dst = copy_fixture("config_layers");
+        let manifest_path = dst.join("Cargo.toml");
+        let config_of = |flags: &[&str], env: &[(&str, &str)]| {
+            let mut args = vec!["cargo-update-dep", "-p", "serde", "-v", "1", "-n", "2"];
+            args.extend(["-m", manifest_path.to_str().unwrap()]);
+            args.extend(flags);
+            let matches = cli().get_matches_from(args);
+            let defaults = Defaults::load(get_metadata(&dst).ok().as_ref(), |name| {
+                env.iter()
+                    .find(|(key, _)| *key == name)
+                    .map(|(_, value)| value.to_string())
+            });
+            let options = Options::from_matches_and_defaults(&matches, defaults);
+            let config = Config {
+                root_dir: &dst,
+                mode: get_mode(&matches),
+                package: matches.value_of("dependency_name"),
+                version: matches.value_of("version"),
+                new_version: matches.value_of("new_version"),
+                set: vec![],
+                options: &options,
+            };
+            serde_json::to_value(&config).unwrap()
+        };
+
+        // the settings of the root manifest apply by default
+        let config = config_of(&[], &[]);
+        assert_eq!(config["dep_kinds"], serde_json::json!(["build"]));
+        assert_eq!(config["exclude_paths"], serde_json::json!(["legacy/"]));
+        assert_eq!(config["ignore_case"], true);
+        assert_eq!(config["strict"], true);
+
+        // the environment overrides the manifest
+        let env = [
+            ("CARGO_UPDATE_DEP_DEP_KINDS", "dev, normal"),
+            ("CARGO_UPDATE_DEP_STRICT", "false"),
+        ];
+        let config = config_of(&[], &env);
+        assert_eq!(config["dep_kinds"], serde_json::json!(["dev", "normal"]));
+        assert_eq!(config["strict"], false);
+        assert_eq!(config["exclude_paths"], serde_json::json!(["legacy/"]));
+
+        // and flags override both
+        let config = config_of(&["--dep-kind", "normal", "--strict"], &env);
+        assert_eq!(config["dep_kinds"], serde_json::json!(["normal"]));
+        assert_eq!(config["strict"], true);
+        assert_eq!(config["ignore_case"], true);
+    }
+
+    #[test]
+    fn test_transactional() {
+        let dst = copy_fixture("dep_kinds");
+        let options = Options {
+            transactional: true,
+            ..Options::default()
+        };
+
+        // prevent the manifest of the second member from being staged
+        fs::create_dir(dst.join("dev/Cargo.toml.cargo-update-dep")).unwrap();
+        let res = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options);
+        assert!(res.is_err());
+
+        // no manifest was modified, and nothing was left behind
+        let normal = fs::read_to_string(dst.join("normal/Cargo.toml")).unwrap();
+        assert!(normal.contains(r#"serde = "1.0.122""#));
+        assert!(!dst.join("normal/Cargo.toml.cargo-update-dep").exists());
+
+        fs::remove_dir(dst.join("dev/Cargo.toml.cargo-update-dep")).unwrap();
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(output.updated_manifests.len(), 2);
+        let dev = fs::read_to_string(dst.join("dev/Cargo.toml")).unwrap();
+        assert!(dev.contains(r#"serde = "1.0.123""#));
+
+        // the second file can be staged but not moved into place, as it is a directory
+        let normal = dst.join("normal/Cargo.toml");
+        let original = fs::read_to_string(&normal).unwrap();
+        let files = [
+            (normal.clone(), "updated".to_string()),
+            (dst.join("dev"), "updated".to_string()),
+        ];
+        let err = write_all_or_nothing(&files).err().unwrap();
+        assert!(err.contains("no manifest was modified"), "{}", err);
+        assert_eq!(fs::read_to_string(&normal).unwrap(), original);
+        assert!(dst.join("dev/Cargo.toml").exists());
+        assert!(!dst.join("normal/Cargo.toml.cargo-update-dep").exists());
+        assert!(!dst.join("dev.cargo-update-dep").exists());
+    }
+
+    #[test]
+    fn test_package_metadata() {
+        let dst = copy_fixture("package_metadata");
+        let options = Options::default();
+
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![dst.join("bumped/Cargo.toml")]
+        );
+        let skipped: Vec<_> = output
+            .skipped_manifests
+            .iter()
+            .map(|skipped| skipped.manifest.clone())
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                dst.join("skipped/Cargo.toml"),
+                dst.join("pinned/Cargo.toml")
+            ]
+        );
+
+        // pins only apply to the given dependencies
+        let output = update_manifests(&dst, "rand", "0.8.0", "0.8.3", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![dst.join("pinned/Cargo.toml")]
+        );
+    }
+
+    #[test]
+    fn test_default_features() {
+        // only the versions change, `default-features = false` is kept as written
+        assert_fixture_update("default_features", &["foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn test_broken_member() {
+        let dst = copy_fixture("broken_member");
+
+        // cargo metadata fails, so the members are read from the root manifest
+        let options = Options::default();
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![
+                dst.join("healthy/Cargo.toml"),
+                dst.join("crates/nested/Cargo.toml")
+            ]
+        );
+        let excluded = fs::read_to_string(dst.join("crates/excluded/Cargo.toml")).unwrap();
+        assert!(excluded.contains(r#"serde = "1.0.122""#));
+
+        let options = Options {
+            strict: true,
+            ..Options::default()
+        };
+        let res = update_manifests(&dst, "serde", "1.0.123", "1.0.124", &options);
+        assert!(res.err().unwrap().contains("cargo metadata failed"));
+    }
+
+    #[test]
+    fn test_workspace_path_dependency() {
+        let dst = copy_fixture("workspace_path");
+        let options = Options::default();
+
+        let output = update_manifests(&dst, "foo", "0.1.1", "0.2.0", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+        assert_eq!(output.inherited_manifests, vec![dst.join("app/Cargo.toml")]);
+
+        // the path is kept, only the version changes
+        let root = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(root.contains(r#"foo = { path = "foo", version = "0.2.0" }"#));
+
+        // the version of the package itself is not a dependency
+        let foo = fs::read_to_string(dst.join("foo/Cargo.toml")).unwrap();
+        assert!(foo.contains(r#"version = "0.1.1""#));
+    }
+
+    #[test]
+    fn test_distant_member() {
+        let dst = copy_fixture("distant_member");
+        let root_dir = dst.join("workspace");
+        let far = dst.join("vendor/nested/far/Cargo.toml");
+
+        // the member is found wherever it lives, as long as cargo lists it
+        let options = Options::default();
+        let output = update_manifests(&root_dir, "rand", "0.7.2", "0.7.3", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![root_dir.join("app/Cargo.toml"), far.clone()]
+        );
+        assert!(fs::read_to_string(&far)
+            .unwrap()
+            .contains(r#"rand = "0.7.3""#));
+
+        // without cargo, the path listed in the root manifest resolves to the same manifest
+        assert_eq!(
+            get_listed_manifest_files(&root_dir),
+            vec![
+                root_dir.join("Cargo.toml"),
+                root_dir.join("app/Cargo.toml"),
+                far
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_tables() {
+        let dst = copy_fixture("unknown_tables");
+        let manifest = dst.join("Cargo.toml");
+        let original = fs::read_to_string(&manifest).unwrap();
+
+        // only the tables of dependencies known to cargo are looked into
+        let output = update_manifests(&dst, "foo", "0.1.1", "0.2.0", &Options::default()).unwrap();
+        assert_eq!(output.updated_manifests, vec![manifest.clone()]);
+        assert_eq!(
+            fs::read_to_string(&manifest).unwrap(),
+            original.replacen(
+                "[dependencies]\nfoo = \"0.1.1\"",
+                "[dependencies]\nfoo = \"0.2.0\"",
+                1
+            )
+        );
+    }
+
+    #[test]
+    fn test_incidental_strings() {
+        // strings containing the name and version outside of the declaration are left alone
+        assert_fixture_update("incidental_strings", &["foo"]);
+    }
+
+    #[test]
+    fn test_spaced_headers() {
+        // TOML allows whitespace around the keys of table headers and dotted keys
+        assert_fixture_update("spaced_headers", &["foo", "bar", "baz", "qux"]);
+    }
+
+    #[test]
+    fn test_inline_root() {
+        // dependency tables can be inline tables at the root of the document
+        assert_fixture_update("inline_root", &["foo", "bar", "baz"]);
+    }
+
+    #[test]
+    fn test_recursive() {
+        let dst = copy_fixture("recursive");
+        let options = Options {
+            no_lock_check: true,
+            ..Options::default()
+        };
+
+        assert_eq!(
+            find_workspaces(&dst),
+            vec![dst.join("first"), dst.join("second")]
+        );
+
+        // every workspace gets its own line of JSON
+        // (the new requirement still matches the path dependencies, so that `cargo update` succeeds)
+        let mut lines = vec![];
+        run_recursive(&dst, "foo", "0.1.1", "0.1", &options, |output| {
+            lines.push(serde_json::to_string(&output).unwrap());
+        });
+        assert_eq!(lines.len(), 2);
+        for (line, workspace) in lines.iter().zip(&["first", "second"]) {
+            assert!(!line.contains('\n'));
+            let output: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(output["workspace"], dst.join(workspace).to_str().unwrap());
+            assert_eq!(output["updated_manifests"].as_array().unwrap().len(), 1);
+
+            // each workspace has its own lockfile, updated in its own context
+            let lock_file = dst.join(workspace).join("Cargo.lock");
+            assert_eq!(output["lock_file"], lock_file.to_str().unwrap());
+            assert_eq!(output["lock_update"]["status"], 0);
+            assert!(lock_file.exists());
+        }
+        assert!(!dst.join("Cargo.lock").exists());
+
+        // each workspace is locked while it's updated
+        let second = dst.join("second");
+        let _lock = lock_workspace(&second, get_metadata(&second).ok().as_ref(), false).unwrap();
+        let options = Options {
+            no_wait: true,
+            ..options
+        };
+        let mut errors = vec![];
+        run_recursive(&dst, "foo", "0.1", "0.1", &options, |output| {
+            errors.push(output.error);
+        });
+        assert!(errors[0].is_none());
+        assert!(errors[1].as_ref().unwrap().contains("--no-wait"));
+    }
+
+    #[test]
+    fn test_set() {
+        assert!(parse_spec("serde=1.0.123").is_ok());
+        assert!(parse_spec("serde=one").is_err());
+        assert!(parse_spec("serde").is_err());
+
+        let dst = copy_fixture("set");
+        let options = Options {
+            discover_glob: Some("Cargo.toml".to_string()),
+            ..Options::default()
+        };
+        let specs = vec![
+            parse_spec("serde=1.0.123").unwrap(),
+            parse_spec("rand = 0.8.3").unwrap(),
+        ];
+
+        let output = run_batch(&dst, &specs, &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+        let transitions: Vec<_> = output
+            .transitions
+            .iter()
+            .map(|t| (t.package.as_str(), t.from.as_str(), t.to.as_str()))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                ("serde", "1.0.100", "1.0.123"),
+                ("serde", "1.0.122", "1.0.123"),
+                ("rand", "0.7", "0.8.3"),
+            ]
+        );
+
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"serde = "1.0.123""#));
+        assert!(manifest.contains(r#"rand = { version = "0.8.3" }"#));
+
+        // cargo update is told which of the locked versions were required
+        let dst = copy_fixture("pin_to_lock");
+        let transitions: Vec<_> = [("rand", "0.7"), ("serde", "1.0"), ("serde", "1.0.100")]
+            .iter()
+            .map(|(package, from)| Transition {
+                manifest: dst.join("Cargo.toml"),
+                package: package.to_string(),
+                from: from.to_string(),
+                to: "2.0".to_string(),
+            })
+            .collect();
+        assert_eq!(
+            transition_pkgids(&dst, &transitions),
+            vec!["rand:0.7.3", "serde:1.0.123"]
+        );
+
+        // and doesn't run when no manifest changed
+        let specs = vec![parse_spec("serde=1.0").unwrap()];
+        let output = run_batch(&dst, &specs, &Options::default()).unwrap();
+        assert!(output.updated_manifests.is_empty());
+        assert!(output.lock_update.is_none());
+    }
+
+    #[test]
+    fn test_ignore_file() {
+        let dst = copy_fixture("ignore_file");
+        let options = Options {
+            exclude_paths: vec!["tools".to_string()],
+            ..Options::default()
+        };
+
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![dst.join("crates/app/Cargo.toml")]
+        );
+        let skipped: Vec<_> = output
+            .skipped_manifests
+            .iter()
+            .filter(|skipped| matches!(skipped.reason, SkipReason::IgnoreRule))
+            .map(|skipped| skipped.manifest.clone())
+            .collect();
+        assert_eq!(
+            skipped,
+            vec![
+                dst.join("crates/legacy/old/Cargo.toml"),
+                dst.join("crates/legacy/older/Cargo.toml"),
+                dst.join("tools/Cargo.toml"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pin_to_lock() {
+        let dst = copy_fixture("pin_to_lock");
+        let options = Options::default();
+
+        // a single version is resolved
+        pin_to_lock(&dst, "serde", None, &options).unwrap();
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"serde = "1.0.123""#));
+
+        // several versions are resolved, the current one must be given
+        assert!(pin_to_lock(&dst, "rand", None, &options).is_err());
+        pin_to_lock(&dst, "rand", Some("0.7"), &options).unwrap();
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"rand = "0.7.3""#));
+        assert!(manifest.contains(r#"rand_new = { package = "rand", version = "0.8" }"#));
+    }
+
+    #[test]
+    fn test_package_id() {
+        let dst = copy_fixture("pin_to_lock");
+        let options = Options::default();
+
+        // the locked version is matched against the requirements of the manifests
+        let (package, requirement) = resolve_package_id(&dst, "serde", &options).unwrap();
+        assert_eq!(
+            package.spec(),
+            "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.123"
+        );
+        assert_eq!(requirement, "1.0");
+        let (package, requirement) = resolve_package_id(
+            &dst,
+            "https://github.com/rust-lang/crates.io-index#rand@0.8",
+            &options,
+        )
+        .unwrap();
+        assert_eq!(
+            package.spec(),
+            "registry+https://github.com/rust-lang/crates.io-index#rand@0.8.3"
+        );
+        assert_eq!(requirement, "0.8");
+        let err = resolve_package_id(&dst, "rand", &options).err().unwrap();
+        assert!(err.contains("rand@0.7.3") && err.contains("rand@0.8.3"));
+
+        // the requirement admitting the locked version is the one updated
+        let (package, version) = resolve_package_id(&dst, "rand@0.8", &options).unwrap();
+        let output = update_manifests(&dst, &package.name, &version, "0.8.4", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+        let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(manifest.contains(r#"rand_new = { package = "rand", version = "0.8.4" }"#));
+        assert!(manifest.contains(r#"rand = "0.7""#));
+
+        // the lockfile doesn't have to agree with the manifests
+        fs::write(dst.join("Cargo.toml"), manifest.replace("0.8.4", "0.9")).unwrap();
+        let err = resolve_package_id(&dst, "rand@0.8", &options)
+            .err()
+            .unwrap();
+        assert!(err.contains("no manifest has a requirement on rand admitting 0.8.3"));
+    }
+
+    #[test]
+    fn test_package_id_registries() {
+        // rand 0.8.3 is locked twice: from crates.io, and from the registry of .cargo/config.toml
+        let specs = [
+            (
+                "https://github.com/rust-lang/crates.io-index#rand@0.8.3",
+                r#"rand = "0.8.4""#,
+                r#"my_rand = { package = "rand", version = "0.8", registry = "my-registry" }"#,
+            ),
+            (
+                "registry+https://my-registry.example.com/index#rand@0.8.3",
+                r#"my_rand = { package = "rand", version = "0.8.4", registry = "my-registry" }"#,
+                r#"rand = "0.8""#,
+            ),
+        ];
+        for (spec, updated, untouched) in specs {
+            let dst = copy_fixture("package_id_registries");
+            let mut options = Options::default();
+            let (package, requirement) = resolve_package_id(&dst, spec, &options).unwrap();
+            assert_eq!(requirement, "0.8");
+
+            options.source = Some(DeclaredSource::of(&dst, &package));
+            options.package_id = Some(package.spec());
+            update_manifests(&dst, &package.name, &requirement, "0.8.4", &options).unwrap();
+            let manifest = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+            assert!(manifest.contains(updated), "{}", manifest);
+            assert!(manifest.contains(untouched), "{}", manifest);
+        }
+
+        // the registry can also be named by the environment
+        let dst = copy_fixture("package_id_registries");
+        fs::remove_dir_all(dst.join(".cargo")).unwrap();
+        let package = lockfile::LockedPackage {
+            name: "rand".to_string(),
+            version: "0.8.3".to_string(),
+            source: Some("registry+https://my-registry.example.com/index/".to_string()),
+            dependencies: vec![],
+        };
+        std::env::set_var(
+            "CARGO_REGISTRIES_MY_REGISTRY_INDEX",
+            "https://my-registry.example.com/index",
+        );
+        let source = DeclaredSource::of(&dst, &package);
+        std::env::remove_var("CARGO_REGISTRIES_MY_REGISTRY_INDEX");
+        assert_eq!(
+            source,
+            DeclaredSource::Registry(vec!["MY_REGISTRY".to_string()])
+        );
+        let dependencies =
+            manifest::dependencies(&fs::read_to_string(dst.join("Cargo.toml")).unwrap());
+        let declared: Vec<_> = dependencies
+            .iter()
+            .filter(|dependency| source.declares(dependency))
+            .map(|dependency| dependency.key.as_str())
+            .collect();
+        assert_eq!(declared, ["my_rand"]);
+    }
+
+    #[test]
+    fn test_explain_resolution() {
+        let dst = copy_fixture("pin_to_lock");
+        let lock = fs::read_to_string(dst.join("Cargo.lock")).unwrap();
+
+        // after bumping rand 0.7 to a range, cargo replaced 0.7.3 with 0.8.4
+        let new_lock = lock.replace(
+            "[[package]]\nname = \"rand\"\nversion = \"0.7.3\"\n",
+            "[[package]]\nname = \"rand\"\nversion = \"0.8.4\"\n",
+        );
+        let resolution = explain_resolution("rand", ">=0.8, <0.9", &lock, &new_lock);
+        assert_eq!(
+            resolution,
+            Resolution {
+                package: "rand".to_string(),
+                requirement: ">=0.8, <0.9".to_string(),
+                before: vec!["0.7.3".to_string(), "0.8.3".to_string()],
+                after: vec!["0.8.4".to_string(), "0.8.3".to_string()],
+                chosen: Some("0.8.4".to_string()),
+            }
+        );
+
+        // nothing locked matches the requirement, for example if cargo update failed
+        let resolution = explain_resolution("rand", "^0.9", &lock, &lock);
+        assert_eq!(resolution.chosen, None);
+    }
+
+    #[test]
+    fn test_default_members_only() {
+        let dst = copy_fixture("default_members");
+        let options = Options {
+            default_members_only: true,
+            ..Options::default()
+        };
+
+        let output = update_manifests(&dst, "serde", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(
+            output.updated_manifests,
+            vec![dst.join("Cargo.toml"), dst.join("app/Cargo.toml")]
+        );
+        let skipped: Vec<_> = output
+            .skipped_manifests
+            .iter()
+            .map(|skipped| skipped.manifest.clone())
+            .collect();
+        assert_eq!(skipped, vec![dst.join("tools/Cargo.toml")]);
+    }
+
+    #[test]
+    fn test_glob_dependency_name() {
+        let dst = copy_fixture("glob_names");
+        let options = Options {
+            no_lock_check: true,
+            no_create_lock: true,
+            skip_workspace_crates: true,
+            ..Options::default()
+        };
+
+        let output = run_glob(&dst, "serde*", "1.0.122", "1.0.123", &options).unwrap();
+        assert_eq!(output.packages, vec!["serde", "serde_derive"]);
+        assert_eq!(output.skipped_workspace_crates, vec!["serde_macros"]);
+        assert_eq!(
+            output.output.updated_manifests,
+            vec![dst.join("app/Cargo.toml")]
+        );
+
+        let app = fs::read_to_string(dst.join("app/Cargo.toml")).unwrap();
+        assert!(app.contains(r#"serde = "1.0.123""#));
+        assert!(app.contains(r#"serde_derive = "1.0.123""#));
+        assert!(app.contains(r#"rand = "1.0.122""#));
+        assert!(app.contains(r#"serde_macros = { path = "../serde_macros", version = "1.0.122" }"#));
+    }
+
+    #[test]
+    #[cfg(feature = "network")]
+    fn test_verify_published() {
+        // a registry serving a single index file
+        let registry = tempfile::tempdir().unwrap();
+        let index_file = registry.path().join(registry::index_path("rand"));
+        fs::create_dir_all(index_file.parent().unwrap()).unwrap();
+        fs::write(
+            index_file,
+            r#"{"name":"rand","vers":"0.7.3","deps":[],"cksum":"","features":{},"yanked":false}"#,
+        )
+        .unwrap();
+        let index_url = format!("file://{}", registry.path().display());
+
+        assert!(verify_published(&index_url, "rand", "0.7.3"));
+        assert!(!verify_published(&index_url, "rand", "0.7.33"));
+        assert!(!verify_published(&index_url, "rnad", "0.7.3"));
+    }
+
+    #[test]
+    fn test_commit() {
+        let dst = copy_fixture("workspace_path");
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .current_dir(&dst)
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+            String::from_utf8_lossy(&output.stdout).trim().to_string()
+        };
+        let options = Options {
+            no_lock_check: true,
+            no_create_lock: true,
+            commit: true,
+            branch: Some("bump-foo".to_string()),
+            push: true,
+            ..Options::default()
+        };
+
+        // outside of a repository, nothing is modified
+        if git::ensure_repository(&dst).is_err() {
+            assert!(run(&dst, "foo", "0.1.1", "0.2.0", &options).is_err());
+            let root = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+            assert!(root.contains(r#"version = "0.1.1""#));
+        }
+
+        // a repository pushing to a local remote
+        let remote = tempfile::tempdir().unwrap();
+        git(&["init", "--quiet", "--bare", remote.path().to_str().unwrap()]);
+        git(&["init", "--quiet"]);
+        git(&["config", "user.name", "test"]);
+        git(&["config", "user.email", "test@example.com"]);
+        git(&["remote", "add", "origin", remote.path().to_str().unwrap()]);
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "initial"]);
+
+        let output = run(&dst, "foo", "0.1.1", "0.2.0", &options).unwrap();
+        let git_output = output.git.unwrap();
+        assert_eq!(git_output.branch.as_deref(), Some("bump-foo"));
+        assert_eq!(git_output.commit.unwrap(), git(&["rev-parse", "HEAD"]));
+        assert!(git_output.pushed);
+
+        assert_eq!(git(&["rev-parse", "--abbrev-ref", "HEAD"]), "bump-foo");
+        assert_eq!(
+            git(&["log", "-1", "--format=%s"]),
+            "Bump foo from 0.1.1 to 0.2.0"
+        );
+        assert_eq!(git(&["status", "--porcelain"]), "");
+    }
+
+    #[test]
+    fn test_require_clean_git() {
+        let dst = copy_fixture("workspace_path");
+        let git = |args: &[&str]| {
+            let output = Command::new("git")
+                .current_dir(&dst)
+                .args(args)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "git {:?} failed", args);
+        };
+        let options = Options {
+            require_clean_git: true,
+            ..Options::default()
+        };
+
+        // outside of a repository, the check is skipped
+        if git::ensure_repository(&dst).is_err() {
+            let output = update_manifests(&dst, "foo", "0.1.1", "0.2.0", &options).unwrap();
+            assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+            fs::write(dst.join("Cargo.toml"), &output.originals[0].1).unwrap();
+        }
+
+        git(&["init", "--quiet"]);
+        git(&["config", "user.name", "test"]);
+        git(&["config", "user.email", "test@example.com"]);
+        git(&["add", "."]);
+        git(&["commit", "--quiet", "-m", "initial"]);
+
+        // a dirty manifest to update aborts the run, and is reported
+        let root = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        fs::write(dst.join("Cargo.toml"), format!("{}\n# wip\n", root)).unwrap();
+        let err = update_manifests(&dst, "foo", "0.1.1", "0.2.0", &options)
+            .err()
+            .unwrap();
+        assert!(err.contains("uncommitted changes"));
+        assert!(err.contains(&dst.join("Cargo.toml").display().to_string()));
+        let root = fs::read_to_string(dst.join("Cargo.toml")).unwrap();
+        assert!(root.contains(r#"version = "0.1.1""#));
+
+        // other dirty files don't matter
+        git(&["commit", "--quiet", "-am", "wip"]);
+        fs::write(dst.join("app/src/lib.rs"), "// wip\n").unwrap();
+        let output = update_manifests(&dst, "foo", "0.1.1", "0.2.0", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+    }
+
+    #[test]
+    fn test_no_create_lock() {
+        let dst = copy_fixture("workspace_path");
+        let options = Options {
+            no_lock_check: true,
+            no_create_lock: true,
+            ..Options::default()
+        };
+
+        // the manifests are updated, but no Cargo.lock is generated
+        let output = run(&dst, "foo", "0.1.1", "0.2.0", &options).unwrap();
+        assert_eq!(output.updated_manifests, vec![dst.join("Cargo.toml")]);
+        assert!(output.lock_update.is_none());
+        assert!(!dst.join("Cargo.lock").exists());
     }
 }
@@ -0,0 +1,844 @@
+//! Locates the dependencies declared in a manifest.
+//!
+//! We don't deserialize manifests with a TOML library, as we want to edit them in place without
+//! reformatting anything. Instead, we scan the manifest and record where each dependency
+//! declaration (and its version requirement) lives.
+
+use std::ops::Range;
+
+/// A string value of the manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Str {
+    pub value: String,
+    /// location of the value in the manifest, quotes excluded
+    pub span: Range<usize>,
+}
+
+/// A dependency declared in a manifest.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Dependency {
+    /// the table containing the dependency (e.g. `["workspace", "dependencies"]`)
+    pub table: Vec<String>,
+    /// the key of the dependency, which is an alias if `package` is set
+    pub key: String,
+    /// the `package` field
+    pub package: Option<Str>,
+    /// the version requirement
+    pub version: Option<Str>,
+    /// `workspace = true`, the dependency is inherited from the workspace
+    pub workspace: bool,
+    /// the `git` field
+    pub git: Option<Str>,
+    /// the git reference, as the name of the field (`tag`, `rev` or `branch`) and its value
+    pub git_ref: Option<(String, Str)>,
+    /// the `registry` field, naming a registry of the cargo configuration
+    pub registry: Option<Str>,
+    /// location of the whole declaration in the manifest
+    pub span: Range<usize>,
+}
+
+impl Dependency {
+    /// The name of the package, which is the key unless the dependency is renamed.
+    pub fn name(&self) -> &str {
+        self.package
+            .as_ref()
+            .map(|p| p.value.as_str())
+            .unwrap_or(&self.key)
+    }
+
+    /// Is the dependency declared in `[workspace.dependencies]`?
+    pub fn in_workspace_table(&self) -> bool {
+        self.table.first().map(String::as_str) == Some("workspace")
+    }
+}
+
+/// The kinds of dependency tables.
+const DEPENDENCY_TABLES: &[&str] = &[
+    "dependencies",
+    "dev-dependencies",
+    "dev_dependencies",
+    "build-dependencies",
+    "build_dependencies",
+];
+
+/// Returns the length of the prefix of `path` naming a table of dependencies, if any.
+/// Any other table (like `[lints]`, or the ones cargo will add) is none of our business.
+fn dependency_table_len(path: &[String]) -> Option<usize> {
+    let is_kind = |segment: Option<&String>| {
+        segment.is_some_and(|segment| DEPENDENCY_TABLES.contains(&segment.as_str()))
+    };
+    match path.first().map(String::as_str) {
+        _ if is_kind(path.first()) => Some(1),
+        Some("workspace") if path.get(1).map(String::as_str) == Some("dependencies") => Some(2),
+        Some("target") if is_kind(path.get(2)) => Some(3),
+        Some("patch") if path.len() >= 2 => Some(2),
+        _ => None,
+    }
+}
+
+/// Returns the dependencies declared in the manifest, in order of appearance.
+pub fn dependencies(content: &str) -> Vec<Dependency> {
+    scan(content).dependencies
+}
+
+/// Returns the array of strings at `path` (e.g. `["workspace", "members"]`), if any.
+pub fn string_array(content: &str, path: &[&str]) -> Option<Vec<String>> {
+    scan(content)
+        .arrays
+        .into_iter()
+        .find(|(array_path, _)| array_path.iter().eq(path))
+        .map(|(_, array)| array)
+}
+
+/// Returns the strings of the document with their path (e.g. `["registries", "foo", "index"]`),
+/// in order of appearance.
+pub fn strings(content: &str) -> Vec<(Vec<String>, String)> {
+    scan(content).strings
+}
+
+fn scan(content: &str) -> Scanner<'_> {
+    let mut scanner = Scanner {
+        src: content,
+        pos: 0,
+        dependencies: vec![],
+        arrays: vec![],
+        strings: vec![],
+    };
+    scanner.scan();
+    scanner
+}
+
+/// Replaces the given spans of `content`. The spans must not overlap.
+pub fn replace(content: &str, mut edits: Vec<(Range<usize>, String)>) -> String {
+    edits.sort_by_key(|(span, _)| span.start);
+    let mut result = String::with_capacity(content.len());
+    let mut pos = 0;
+    for (span, replacement) in edits {
+        result.push_str(&content[pos..span.start]);
+        result.push_str(&replacement);
+        pos = span.end;
+    }
+    result.push_str(&content[pos..]);
+    result
+}
+
+/// Returns the span of the inline table declaring `dependency` (like `foo = { version = "1", }`)
+/// along with its canonical form (`{ version = "1" }`): one space inside the braces and after
+/// commas, one around `=`, and no trailing comma. Keys and values are kept as they are.
+/// Returns `None` if the dependency isn't declared with an inline table, or if the table contains
+/// comments, which would be lost.
+pub fn normalize_inline_table(
+    content: &str,
+    dependency: &Dependency,
+) -> Option<(Range<usize>, String)> {
+    let mut scanner = Scanner {
+        src: content,
+        pos: dependency.span.start,
+        dependencies: vec![],
+        arrays: vec![],
+        strings: vec![],
+    };
+    scanner.key()?;
+    scanner.skip_whitespace(false);
+    if !scanner.eat('=') {
+        return None;
+    }
+    scanner.skip_whitespace(false);
+    let start = scanner.pos;
+    let entries = match scanner.value()? {
+        Value::Table(entries) => entries,
+        _ => return None,
+    };
+    // dotted keys spread the declaration over several statements
+    let span = start..scanner.pos;
+    if span.end != dependency.span.end {
+        return None;
+    }
+
+    let mut fields = vec![];
+    let mut pos = span.start + 1;
+    for (_, _, entry) in entries {
+        if content[pos..entry.start].contains('#') {
+            return None;
+        }
+        scanner.pos = entry.start;
+        scanner.key()?;
+        let key = content[entry.start..scanner.pos].trim_end();
+        scanner.skip_whitespace(false);
+        scanner.eat('=');
+        scanner.skip_whitespace(false);
+        fields.push(format!("{} = {}", key, &content[scanner.pos..entry.end]));
+        pos = entry.end;
+    }
+    if content[pos..span.end].contains('#') {
+        return None;
+    }
+    let normalized = if fields.is_empty() {
+        "{}".to_string()
+    } else {
+        format!("{{ {} }}", fields.join(", "))
+    };
+    Some((span, normalized))
+}
+
+/// Returns the (0-indexed) line containing the byte `offset` of `content`.
+pub fn line_of(content: &str, offset: usize) -> usize {
+    content[..offset].matches('\n').count()
+}
+
+/// A parsed TOML value, only keeping what we care about.
+enum Value {
+    Str(Str),
+    Bool(bool),
+    Table(Vec<(Vec<String>, Value, Range<usize>)>),
+    Array(Vec<Value>),
+    Other,
+}
+
+struct Scanner<'a> {
+    src: &'a str,
+    pos: usize,
+    dependencies: Vec<Dependency>,
+    /// the arrays of strings, by path
+    arrays: Vec<(Vec<String>, Vec<String>)>,
+    /// the strings, by path
+    strings: Vec<(Vec<String>, String)>,
+}
+
+impl<'a> Scanner<'a> {
+    fn scan(&mut self) {
+        // the current table, `None` in arrays of tables
+        let mut table = Some(vec![]);
+        loop {
+            self.skip_whitespace(true);
+            let start = self.pos;
+            match self.peek() {
+                None => break,
+                Some('[') if self.src[self.pos..].starts_with("[[") => {
+                    table = None;
+                    self.skip_line();
+                }
+                Some('[') => {
+                    self.pos += 1;
+                    table = self.key().filter(|_| {
+                        self.skip_whitespace(false);
+                        self.eat(']')
+                    });
+                    if let Some(path) = &table {
+                        self.visit(path, &[], None, start..self.pos);
+                    }
+                    self.skip_line();
+                }
+                Some(_) => {
+                    let statement = self.key().and_then(|key| {
+                        self.skip_whitespace(false);
+                        if !self.eat('=') {
+                            return None;
+                        }
+                        self.value().map(|value| (key, value))
+                    });
+                    match (statement, &table) {
+                        (Some((key, value)), Some(table)) => {
+                            let path = table.iter().chain(&key).cloned().collect();
+                            match &value {
+                                Value::Array(values) => {
+                                    let strings = values.iter().filter_map(|value| match value {
+                                        Value::Str(string) => Some(string.value.clone()),
+                                        _ => None,
+                                    });
+                                    self.arrays.push((path, strings.collect()));
+                                }
+                                Value::Str(string) => {
+                                    self.strings.push((path, string.value.clone()));
+                                }
+                                _ => (),
+                            }
+                            let span = start..self.pos;
+                            self.visit(table, &key, Some(value), span);
+                            self.skip_line();
+                        }
+                        (Some(_), None) => self.skip_line(),
+                        // not valid TOML, try again on the next line
+                        (None, _) => {
+                            self.pos = start;
+                            self.skip_line();
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records what the statement `key = value` in `table` declares.
+    /// Table headers are visited with an empty key and no value.
+    fn visit(
+        &mut self,
+        table: &[String],
+        key: &[String],
+        value: Option<Value>,
+        span: Range<usize>,
+    ) {
+        let path: Vec<String> = table.iter().chain(key).cloned().collect();
+        let len = match dependency_table_len(&path) {
+            Some(len) => len,
+            None => return,
+        };
+        let (table, rest) = path.split_at(len);
+        match (rest, value) {
+            // an inline table of dependencies (e.g. `dependencies = { foo = "1" }`)
+            ([], Some(Value::Table(entries))) => {
+                for (key, value, span) in entries {
+                    self.visit(table, &key, Some(value), span);
+                }
+            }
+            ([name], None) => {
+                self.dependency(table, name, span);
+            }
+            ([name], Some(Value::Str(version))) => {
+                self.dependency(table, name, span).version = Some(version);
+            }
+            ([name], Some(Value::Table(fields))) => {
+                self.dependency(table, name, span.clone());
+                for (field, value, _) in fields {
+                    if let [field] = field.as_slice() {
+                        self.field(table, name, field, value, span.clone());
+                    }
+                }
+            }
+            ([name, field], Some(value)) => {
+                self.field(table, name, field, value, span);
+            }
+            _ => (),
+        }
+    }
+
+    /// Sets the `field` of a dependency.
+    fn field(
+        &mut self,
+        table: &[String],
+        name: &str,
+        field: &str,
+        value: Value,
+        span: Range<usize>,
+    ) {
+        let dependency = self.dependency(table, name, span);
+        match (field, value) {
+            ("version", Value::Str(value)) => dependency.version = Some(value),
+            ("package", Value::Str(value)) => dependency.package = Some(value),
+            ("git", Value::Str(value)) => dependency.git = Some(value),
+            ("tag" | "rev" | "branch", Value::Str(value)) => {
+                dependency.git_ref = Some((field.to_string(), value))
+            }
+            ("registry", Value::Str(value)) => dependency.registry = Some(value),
+            ("workspace", Value::Bool(value)) => dependency.workspace = value,
+            _ => (),
+        }
+    }
+
+    /// Returns the dependency `name` of `table`, creating it if needed, and extends its span.
+    fn dependency(&mut self, table: &[String], name: &str, span: Range<usize>) -> &mut Dependency {
+        let index = self
+            .dependencies
+            .iter()
+            .position(|dependency| dependency.table == table && dependency.key == name);
+        let index = index.unwrap_or_else(|| {
+            self.dependencies.push(Dependency {
+                table: table.to_vec(),
+                key: name.to_string(),
+                package: None,
+                version: None,
+                workspace: false,
+                git: None,
+                git_ref: None,
+                registry: None,
+                span: span.clone(),
+            });
+            self.dependencies.len() - 1
+        });
+        let dependency = &mut self.dependencies[index];
+        dependency.span.start = dependency.span.start.min(span.start);
+        dependency.span.end = dependency.span.end.max(span.end);
+        dependency
+    }
+
+    //
+    // lexing
+    //
+
+    fn peek(&self) -> Option<char> {
+        self.src[self.pos..].chars().next()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skips whitespace and comments, and newlines if `newlines` is set.
+    fn skip_whitespace(&mut self, newlines: bool) {
+        while let Some(c) = self.peek() {
+            match c {
+                ' ' | '\t' => self.pos += 1,
+                '\r' | '\n' if newlines => self.pos += 1,
+                '#' => {
+                    let len = self.src[self.pos..]
+                        .find('\n')
+                        .unwrap_or(self.src.len() - self.pos);
+                    self.pos += len;
+                }
+                _ => break,
+            }
+        }
+    }
+
+    /// Skips to the beginning of the next line.
+    fn skip_line(&mut self) {
+        match self.src[self.pos..].find('\n') {
+            Some(len) => self.pos += len + 1,
+            None => self.pos = self.src.len(),
+        }
+    }
+
+    /// Parses a (potentially dotted) key.
+    fn key(&mut self) -> Option<Vec<String>> {
+        let mut key = vec![];
+        loop {
+            self.skip_whitespace(false);
+            let segment = match self.peek()? {
+                '"' | '\'' => self.string()?.value,
+                _ => {
+                    let len = self.src[self.pos..]
+                        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-' || c == '_'))
+                        .unwrap_or(self.src.len() - self.pos);
+                    if len == 0 {
+                        return None;
+                    }
+                    self.pos += len;
+                    self.src[self.pos - len..self.pos].to_string()
+                }
+            };
+            key.push(segment);
+            self.skip_whitespace(false);
+            if !self.eat('.') {
+                return Some(key);
+            }
+        }
+    }
+
+    /// Parses a value.
+    fn value(&mut self) -> Option<Value> {
+        self.skip_whitespace(false);
+        match self.peek()? {
+            '"' | '\'' => self.string().map(Value::Str),
+            '{' => {
+                self.pos += 1;
+                let mut entries = vec![];
+                loop {
+                    self.skip_whitespace(true);
+                    if self.eat('}') {
+                        return Some(Value::Table(entries));
+                    }
+                    let start = self.pos;
+                    let key = self.key()?;
+                    self.skip_whitespace(false);
+                    if !self.eat('=') {
+                        return None;
+                    }
+                    let value = self.value()?;
+                    entries.push((key, value, start..self.pos));
+                    self.skip_whitespace(true);
+                    self.eat(',');
+                }
+            }
+            '[' => {
+                self.pos += 1;
+                let mut values = vec![];
+                loop {
+                    self.skip_whitespace(true);
+                    if self.eat(']') {
+                        return Some(Value::Array(values));
+                    }
+                    values.push(self.value()?);
+                    self.skip_whitespace(true);
+                    self.eat(',');
+                }
+            }
+            _ => {
+                // booleans, numbers, dates
+                let len = self.src[self.pos..]
+                    .find([',', ']', '}', '#', '\r', '\n'])
+                    .unwrap_or(self.src.len() - self.pos);
+                let token = self.src[self.pos..self.pos + len].trim_end();
+                if token.is_empty() {
+                    return None;
+                }
+                self.pos += token.len();
+                match token {
+                    "true" => Some(Value::Bool(true)),
+                    "false" => Some(Value::Bool(false)),
+                    _ => Some(Value::Other),
+                }
+            }
+        }
+    }
+
+    /// Parses a basic or literal string, potentially multi-line.
+    fn string(&mut self) -> Option<Str> {
+        let quote = self.peek()?;
+        let multiline = if quote == '"' { "\"\"\"" } else { "'''" };
+        let delimiter = if self.src[self.pos..].starts_with(multiline) {
+            multiline
+        } else if quote == '"' {
+            "\""
+        } else {
+            "'"
+        };
+        self.pos += delimiter.len();
+        // a newline right after the opening delimiter of a multi-line string isn't part of it
+        if delimiter == multiline {
+            if self.src[self.pos..].starts_with("\r\n") {
+                self.pos += 2;
+            } else if self.src[self.pos..].starts_with('\n') {
+                self.pos += 1;
+            }
+        }
+        let start = self.pos;
+
+        let mut value = String::new();
+        let mut chars = self.src[start..].char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            if self.src[start + i..].starts_with(delimiter) {
+                // one or two quotes can precede the closing delimiter of a multi-line string
+                let extra = if delimiter == multiline {
+                    let rest = &self.src[start + i..];
+                    (rest.len() - rest.trim_start_matches(quote).len()).min(5) - 3
+                } else {
+                    0
+                };
+                value.extend(std::iter::repeat_n(quote, extra));
+                let end = start + i + extra;
+                self.pos = end + delimiter.len();
+                return Some(Str {
+                    value,
+                    span: start..end,
+                });
+            }
+            match c {
+                '\n' if delimiter.len() == 1 => return None,
+                '\\' if quote == '"' => match chars.next()?.1 {
+                    'b' => value.push('\u{8}'),
+                    't' => value.push('\t'),
+                    'n' => value.push('\n'),
+                    'f' => value.push('\u{c}'),
+                    'r' => value.push('\r'),
+                    '"' => value.push('"'),
+                    '\\' => value.push('\\'),
+                    escaped @ ('u' | 'U') => {
+                        let len = if escaped == 'u' { 4 } else { 8 };
+                        let digits = self.src.get(start + i + 2..start + i + 2 + len)?;
+                        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+                            return None;
+                        }
+                        value.push(char::from_u32(u32::from_str_radix(digits, 16).ok()?)?);
+                        chars.nth(len - 1);
+                    }
+                    // a backslash ending a line of a multi-line string trims the whitespace
+                    // (newlines included) up to the next character
+                    ' ' | '\t' | '\r' | '\n' if delimiter.len() == 3 => {
+                        let rest = &self.src[start + i + 1..];
+                        let trimmed = rest.trim_start_matches([' ', '\t', '\r', '\n']);
+                        if !rest[..rest.len() - trimmed.len()].contains('\n') {
+                            return None;
+                        }
+                        while chars.next_if(|(_, c)| " \t\r\n".contains(*c)).is_some() {}
+                    }
+                    _ => return None,
+                },
+                c => value.push(c),
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dependencies() {
+        let content = r#"
+[package]
+name = "foo" # not a dependency
+version = "1.0.0"
+
+[dependencies]
+serde = { version = "1.0", features = ["derive"] }
+json = { package = "serde_json", version = '1.0.60' }
+rand.workspace = true
+
+[target.'cfg(unix)'.dev-dependencies.regex]
+version = "1.4"
+git = "https://github.com/rust-lang/regex"
+tag = "1.4.3"
+
+[workspace.dependencies]
+rand = "0.8"
+"#;
+        let deps = dependencies(content);
+        let summary: Vec<_> = deps
+            .iter()
+            .map(|dep| {
+                (
+                    dep.table.join("."),
+                    dep.name(),
+                    dep.version.as_ref().map(|v| v.value.as_str()),
+                    dep.workspace,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("dependencies".to_string(), "serde", Some("1.0"), false),
+                (
+                    "dependencies".to_string(),
+                    "serde_json",
+                    Some("1.0.60"),
+                    false
+                ),
+                ("dependencies".to_string(), "rand", None, true),
+                (
+                    "target.cfg(unix).dev-dependencies".to_string(),
+                    "regex",
+                    Some("1.4"),
+                    false
+                ),
+                (
+                    "workspace.dependencies".to_string(),
+                    "rand",
+                    Some("0.8"),
+                    false
+                ),
+            ]
+        );
+
+        // spans point inside the quotes
+        assert_eq!(
+            &content[deps[1].version.as_ref().unwrap().span.clone()],
+            "1.0.60"
+        );
+        assert!(content[deps[3].span.clone()].starts_with("[target."));
+        assert!(content[deps[3].span.clone()].ends_with("1.4.3\""));
+        let (field, git_ref) = deps[3].git_ref.as_ref().unwrap();
+        assert_eq!((field.as_str(), git_ref.value.as_str()), ("tag", "1.4.3"));
+    }
+
+    #[test]
+    fn test_normalize_inline_table() {
+        let content = r#"
+[dependencies]
+a = {version="1",   features = ["std", "derive"], }
+b.version = "1"
+b.features = ["std"]
+c = { version = "1", # why
+}
+d = { "version"  =  '1' ,package="e"}
+"#;
+        let deps = dependencies(content);
+        let normalized: Vec<_> = deps
+            .iter()
+            .map(|dep| normalize_inline_table(content, dep).map(|(_, table)| table))
+            .collect();
+        assert_eq!(
+            normalized,
+            vec![
+                Some(r#"{ version = "1", features = ["std", "derive"] }"#.to_string()),
+                None,
+                None,
+                Some(r#"{ "version" = '1', package = "e" }"#.to_string()),
+            ]
+        );
+
+        let (span, _) = normalize_inline_table(content, &deps[0]).unwrap();
+        assert!(content[span].ends_with(", }"));
+    }
+
+    #[test]
+    fn test_string_array() {
+        let content = r#"
+[workspace]
+members = [
+    "a", # first
+    'crates/*',
+]
+"#;
+        let members = string_array(content, &["workspace", "members"]);
+        assert_eq!(members, Some(vec!["a".to_string(), "crates/*".to_string()]));
+        assert_eq!(string_array(content, &["workspace", "exclude"]), None);
+    }
+    #[test]
+    fn test_string_escapes() {
+        let content = r#"
+[escapes]
+basic = "tab\tquote\" backslash\\ \u00e9 \U0001F600 \b\f\r\n"
+literal = 'C:\path\n'
+invalid = "\x41"
+surrogate = "\uD800"
+short = "\u00e"
+after = "ok"
+"#;
+        let found = strings(content);
+        let values: Vec<_> = found
+            .iter()
+            .map(|(path, value)| (path.join("."), value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (
+                    "escapes.basic".to_string(),
+                    "tab\tquote\" backslash\\ \u{e9} \u{1F600} \u{8}\u{c}\r\n"
+                ),
+                ("escapes.literal".to_string(), "C:\\path\\n"),
+                // the invalid escapes are skipped along with their line
+                ("escapes.after".to_string(), "ok"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiline_strings() {
+        let content = r#"
+[package]
+description = """
+[dependencies]
+fake = "1"
+"""
+continued = """
+first \
+    second"""
+quotes = """a "quoted" word"""""
+literal = '''
+no \escapes'''
+
+[dependencies]
+real = """1.0"""
+"#;
+        let found = strings(content);
+        let values: Vec<_> = found
+            .iter()
+            .map(|(path, value)| (path.join("."), value.as_str()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                (
+                    "package.description".to_string(),
+                    "[dependencies]\nfake = \"1\"\n"
+                ),
+                ("package.continued".to_string(), "first second"),
+                ("package.quotes".to_string(), "a \"quoted\" word\"\""),
+                ("package.literal".to_string(), "no \\escapes"),
+                ("dependencies.real".to_string(), "1.0"),
+            ]
+        );
+
+        // the contents of strings aren't mistaken for declarations
+        let deps = dependencies(content);
+        assert_eq!(deps.len(), 1);
+        assert_eq!(deps[0].key, "real");
+        assert_eq!(
+            &content[deps[0].version.as_ref().unwrap().span.clone()],
+            "1.0"
+        );
+
+        // whitespace can follow the line ending backslash, but nothing else
+        let content = "a = \"\"\"one \\ \t\r\n  two\"\"\"\nb = \"\"\"one \\ two\"\"\"\n";
+        assert_eq!(
+            strings(content),
+            vec![(vec!["a".to_string()], "one two".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_dotted_keys() {
+        let content = r#"
+[dependencies]
+serde . version = "1"
+"serde".features = ["derive"]
+'my-json' = { package = "serde_json", version = "1" }
+
+[ dependencies . "rand" ]
+version = "0.8"
+
+[target."cfg(target_os = \"linux\")".dependencies]
+libc.workspace = true
+
+[package.metadata.a."b.c"]
+d = "e"
+"#;
+        let deps = dependencies(content);
+        let summary: Vec<_> = deps
+            .iter()
+            .map(|dep| {
+                (
+                    dep.table.join("|"),
+                    dep.key.as_str(),
+                    dep.name(),
+                    dep.version.as_ref().map(|v| v.value.as_str()),
+                    dep.workspace,
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    "dependencies".to_string(),
+                    "serde",
+                    "serde",
+                    Some("1"),
+                    false
+                ),
+                (
+                    "dependencies".to_string(),
+                    "my-json",
+                    "serde_json",
+                    Some("1"),
+                    false
+                ),
+                (
+                    "dependencies".to_string(),
+                    "rand",
+                    "rand",
+                    Some("0.8"),
+                    false
+                ),
+                (
+                    "target|cfg(target_os = \"linux\")|dependencies".to_string(),
+                    "libc",
+                    "libc",
+                    None,
+                    true
+                ),
+            ]
+        );
+        // the dotted declarations of a dependency are spanned together
+        assert!(content[deps[0].span.clone()].starts_with("serde . version"));
+        assert!(content[deps[0].span.clone()].ends_with(r#"["derive"]"#));
+
+        assert_eq!(
+            string_array(content, &["dependencies", "serde", "features"]),
+            Some(vec!["derive".to_string()])
+        );
+        let path = ["package", "metadata", "a", "b.c", "d"];
+        assert!(strings(content)
+            .iter()
+            .any(|(key, value)| key.iter().eq(&path) && value == "e"));
+    }
+}
@@ -0,0 +1,418 @@
+//! Queries the sparse index of a registry.
+
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Duration;
+
+/// The sparse index of crates.io.
+pub const CRATES_IO_INDEX: &str = "https://index.crates.io";
+
+/// A version of a crate, as listed in the index.
+#[derive(Debug, serde::Deserialize)]
+pub struct IndexVersion {
+    #[serde(rename = "vers")]
+    pub version: String,
+    #[serde(default)]
+    pub yanked: bool,
+}
+
+/// A directory keeping the index files fetched from the registries,
+/// so that they're only fetched again once they're older than `ttl`.
+pub struct IndexCache {
+    pub dir: PathBuf,
+    pub ttl: Duration,
+}
+
+impl IndexCache {
+    /// Returns the path of the cached index file of the crate `name`, one directory per registry.
+    fn path(&self, index_url: &str, name: &str) -> PathBuf {
+        let registry: String = index_url
+            .split("://")
+            .last()
+            .unwrap_or(index_url)
+            .chars()
+            .map(|c| match c {
+                'a'..='z' | 'A'..='Z' | '0'..='9' | '.' | '-' => c,
+                _ => '_',
+            })
+            .collect();
+        self.dir.join(registry).join(index_path(name))
+    }
+
+    /// Returns the cached index file of the crate `name`, unless it's older than the TTL
+    /// and `any_age` isn't set.
+    fn get(&self, index_url: &str, name: &str, any_age: bool) -> Option<String> {
+        let path = self.path(index_url, name);
+        let modified = fs::metadata(&path).ok()?.modified().ok()?;
+        let age = modified.elapsed().unwrap_or_default();
+        if age >= self.ttl && !any_age {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    fn put(&self, index_url: &str, name: &str, content: &str) -> Result<(), String> {
+        let path = self.path(index_url, name);
+        fs::create_dir_all(path.parent().expect("Failed to get cache directory"))
+            .and_then(|_| fs::write(&path, content))
+            .map_err(|err| format!("could not write {}: {}", path.display(), err))
+    }
+}
+
+/// Is cargo told to stay offline?
+pub fn is_offline() -> bool {
+    std::env::var("CARGO_NET_OFFLINE").is_ok_and(|offline| offline == "true")
+}
+
+/// Returns the path of the index file of the crate `name`, as laid out by cargo.
+pub fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    match name.len() {
+        1 => format!("1/{}", name),
+        2 => format!("2/{}", name),
+        3 => format!("3/{}/{}", &name[..1], name),
+        _ => format!("{}/{}/{}", &name[..2], &name[2..4], name),
+    }
+}
+
+/// The error when `curl` isn't installed.
+const MISSING_CURL: &str = "could not find curl, which is needed to query the registry";
+
+/// Describes the error of a `curl` that couldn't be run.
+fn curl_error(err: std::io::Error) -> String {
+    match err.kind() {
+        std::io::ErrorKind::NotFound => MISSING_CURL.to_string(),
+        _ => format!("could not run curl: {}", err),
+    }
+}
+
+/// Fetches the index file of the crate `name` with `curl`.
+pub fn fetch(index_url: &str, name: &str) -> Result<String, String> {
+    let url = format!("{}/{}", index_url.trim_end_matches('/'), index_path(name));
+    let output = Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "--location", &url])
+        .output()
+        .map_err(curl_error)?;
+    if !output.status.success() {
+        return Err(format!(
+            "could not fetch {}: {}",
+            url,
+            String::from_utf8_lossy(&output.stderr).trim()
+        ));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Fetches the index files of the crates `names`, in that order.
+/// The files younger than the TTL of the `cache` are taken from it, and when `offline`
+/// all of them are. Fails if `curl` isn't installed.
+pub fn fetch_all(
+    index_url: &str,
+    names: &[String],
+    cache: Option<&IndexCache>,
+    offline: bool,
+) -> Result<Vec<Result<String, String>>, String> {
+    let cached: Vec<_> = names
+        .iter()
+        .map(|name| cache.and_then(|cache| cache.get(index_url, name, offline)))
+        .collect();
+    let missing: Vec<_> = names
+        .iter()
+        .zip(&cached)
+        .filter(|(_, content)| content.is_none())
+        .map(|(name, _)| name.clone())
+        .collect();
+    let mut fetched = if offline || missing.is_empty() {
+        vec![]
+    } else {
+        fetch_uncached(index_url, &missing)?
+    }
+    .into_iter();
+
+    let mut contents = vec![];
+    for (name, content) in names.iter().zip(cached) {
+        if let Some(content) = content {
+            contents.push(Ok(content));
+            continue;
+        }
+        let content = fetched.next().unwrap_or_else(|| {
+            Err(format!(
+                "{} is not in the index cache and cargo is offline",
+                name
+            ))
+        });
+        if let (Some(cache), Ok(content)) = (cache, &content) {
+            if let Err(err) = cache.put(index_url, name, content) {
+                eprintln!("warning: {}", err);
+            }
+        }
+        contents.push(content);
+    }
+    Ok(contents)
+}
+
+/// Fetches the index files of the crates `names`, in that order.
+/// A single `curl` process fetches all of them, reusing its connection to the registry.
+/// If it can't (an old `curl` for example), each file is fetched on its own.
+fn fetch_uncached(
+    index_url: &str,
+    names: &[String],
+) -> Result<Vec<Result<String, String>>, String> {
+    match fetch_batch(index_url, names) {
+        Ok(contents) => Ok(contents),
+        Err(err) if err == MISSING_CURL => Err(err),
+        Err(err) => {
+            eprintln!(
+                "warning: {}, fetching the index files one by one instead",
+                err
+            );
+            Ok(names.iter().map(|name| fetch(index_url, name)).collect())
+        }
+    }
+}
+
+/// Written by `curl` after each transfer, to tell them apart.
+const TRANSFER_END: &str = "\n@@cargo-update-dep@@";
+
+fn fetch_batch(index_url: &str, names: &[String]) -> Result<Vec<Result<String, String>>, String> {
+    use std::io::Write;
+    use std::process::Stdio;
+
+    let urls: Vec<_> = names
+        .iter()
+        .map(|name| format!("{}/{}", index_url.trim_end_matches('/'), index_path(name)))
+        .collect();
+    // the URLs are passed as a config file on stdin, as there can be a lot of them
+    let config: String = urls
+        .iter()
+        .map(|url| format!("url = \"{}\"\n", url))
+        .collect();
+    let write_out = format!("{} %{{http_code}} %{{exitcode}}\n", TRANSFER_END);
+    let mut child = Command::new("curl")
+        .args([
+            "--silent",
+            "--location",
+            "--write-out",
+            &write_out,
+            "--config",
+            "-",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .map_err(curl_error)?;
+    child
+        .stdin
+        .take()
+        .expect("Failed to open curl stdin")
+        .write_all(config.as_bytes())
+        .map_err(|err| format!("could not pass the URLs to curl: {}", err))?;
+    let output = child
+        .wait_with_output()
+        .map_err(|err| format!("could not run curl: {}", err))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    // every transfer ends with its status: `@@cargo-update-dep@@ <http code> <curl exit code>`
+    let mut contents = vec![];
+    let mut rest = stdout.as_ref();
+    for url in &urls {
+        let (content, status) = rest
+            .split_once(TRANSFER_END)
+            .ok_or("could not fetch the index files in one go")?;
+        let (status, next) = status.split_once('\n').unwrap_or((status, ""));
+        rest = next;
+        let result = match status.split_whitespace().collect::<Vec<_>>().as_slice() {
+            // local registries have no HTTP status
+            ["200" | "000", "0"] => Ok(content.to_string()),
+            [_, "0"] => Err(format!("could not fetch {}: HTTP {}", url, status.trim())),
+            [_, exit_code] if exit_code.parse::<u32>().is_ok() => Err(format!(
+                "could not fetch {}: curl failed with code {}",
+                url, exit_code
+            )),
+            _ => return Err("could not fetch the index files in one go".to_string()),
+        };
+        contents.push(result);
+    }
+    Ok(contents)
+}
+
+/// Returns the versions listed in an index file (one JSON object per line).
+pub fn versions(content: &str) -> Vec<IndexVersion> {
+    content
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+/// Is `version` a published version?
+/// If it's a requirement (like `1.0`) rather than a version, is a non-yanked version matching it?
+pub fn is_published(versions: &[IndexVersion], version: &str) -> bool {
+    if semver::Version::parse(version).is_ok() {
+        return versions
+            .iter()
+            .any(|published| published.version == version);
+    }
+    let requirement = match semver::VersionReq::parse(version) {
+        Ok(requirement) => requirement,
+        Err(_) => return false,
+    };
+    versions.iter().any(|published| {
+        !published.yanked
+            && semver::Version::parse(&published.version)
+                .is_ok_and(|published| requirement.matches(&published))
+    })
+}
+
+/// Returns the newest version matching the `requirement`, ignoring yanked versions.
+pub fn newest_matching(versions: &[IndexVersion], requirement: &str) -> Option<String> {
+    let requirement = semver::VersionReq::parse(requirement).ok()?;
+    versions
+        .iter()
+        .filter(|published| !published.yanked)
+        .filter_map(|published| semver::Version::parse(&published.version).ok())
+        .filter(|published| requirement.matches(published))
+        .max()
+        .map(|newest| newest.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_path() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("cc"), "2/cc");
+        assert_eq!(index_path("syn"), "3/s/syn");
+        assert_eq!(index_path("Serde"), "se/rd/serde");
+    }
+
+    #[test]
+    fn test_fetch_all() {
+        let registry = tempfile::tempdir().unwrap();
+        for (name, version) in [("rand", "0.7.3"), ("serde", "1.0.123"), ("cc", "1.0.0")] {
+            let index_file = registry.path().join(index_path(name));
+            std::fs::create_dir_all(index_file.parent().unwrap()).unwrap();
+            let line = format!(r#"{{"name":"{}","vers":"{}"}}"#, name, version);
+            std::fs::write(index_file, format!("{}\n", line)).unwrap();
+        }
+        let index_url = format!("file://{}", registry.path().display());
+
+        let names: Vec<_> = ["serde", "missing", "cc", "rand"]
+            .iter()
+            .map(|name| name.to_string())
+            .collect();
+        let contents = fetch_batch(&index_url, &names).unwrap();
+        let found: Vec<_> = contents
+            .iter()
+            .map(|content| {
+                content
+                    .as_ref()
+                    .ok()
+                    .map(|content| versions(content)[0].version.clone())
+            })
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                Some("1.0.123".to_string()),
+                None,
+                Some("1.0.0".to_string()),
+                Some("0.7.3".to_string())
+            ]
+        );
+
+        // the fallback gets the same results
+        let one_by_one: Vec<_> = names
+            .iter()
+            .map(|name| fetch(&index_url, name).ok())
+            .collect();
+        let batched: Vec<_> = fetch_all(&index_url, &names, None, false)
+            .unwrap()
+            .into_iter()
+            .map(Result::ok)
+            .collect();
+        assert_eq!(batched, one_by_one);
+    }
+
+    #[test]
+    fn test_index_cache() {
+        let registry = tempfile::tempdir().unwrap();
+        let index_file = registry.path().join(index_path("rand"));
+        std::fs::create_dir_all(index_file.parent().unwrap()).unwrap();
+        std::fs::write(&index_file, r#"{"name":"rand","vers":"0.7.3"}"#).unwrap();
+        let index_url = format!("file://{}", registry.path().display());
+        let dir = tempfile::tempdir().unwrap();
+        let cache = IndexCache {
+            dir: dir.path().to_path_buf(),
+            ttl: Duration::from_secs(3600),
+        };
+        let names = vec!["rand".to_string(), "serde".to_string()];
+        let newest = |contents: Vec<Result<String, String>>| -> Vec<Option<String>> {
+            contents
+                .into_iter()
+                .map(|content| {
+                    content
+                        .ok()
+                        .map(|content| versions(&content)[0].version.clone())
+                })
+                .collect()
+        };
+
+        // the fetched files are cached, and used while they're fresh
+        let contents = fetch_all(&index_url, &names, Some(&cache), false).unwrap();
+        assert_eq!(newest(contents), vec![Some("0.7.3".to_string()), None]);
+        std::fs::write(&index_file, r#"{"name":"rand","vers":"0.8.3"}"#).unwrap();
+        let contents = fetch_all(&index_url, &names, Some(&cache), false).unwrap();
+        assert_eq!(newest(contents), vec![Some("0.7.3".to_string()), None]);
+
+        // offline, the cache is used whatever its age
+        let stale = IndexCache {
+            ttl: Duration::ZERO,
+            ..cache
+        };
+        let contents = fetch_all(&index_url, &names, Some(&stale), true).unwrap();
+        assert_eq!(newest(contents), vec![Some("0.7.3".to_string()), None]);
+
+        // and stale files are fetched again online
+        let contents = fetch_all(&index_url, &names, Some(&stale), false).unwrap();
+        assert_eq!(newest(contents), vec![Some("0.8.3".to_string()), None]);
+    }
+
+    #[test]
+    fn test_is_published() {
+        // a response of the index, trimmed down
+        let content = r#"{"name":"rand","vers":"0.7.2","deps":[],"cksum":"","features":{},"yanked":false}
+{"name":"rand","vers":"0.7.3","deps":[],"cksum":"","features":{},"yanked":false}
+{"name":"rand","vers":"0.8.0","deps":[],"cksum":"","features":{},"yanked":true}
+"#;
+        let versions = versions(content);
+        assert_eq!(versions.len(), 3);
+
+        assert!(is_published(&versions, "0.7.3"));
+        assert!(is_published(&versions, "0.8.0"));
+        assert!(!is_published(&versions, "0.7.33"));
+
+        // requirements only match versions that can still be used
+        assert!(is_published(&versions, "0.7"));
+        assert!(!is_published(&versions, "0.8"));
+    }
+
+    #[test]
+    fn test_newest_matching() {
+        let content = r#"{"name":"rand","vers":"0.7.3","yanked":false}
+{"name":"rand","vers":"0.7.4","yanked":true}
+{"name":"rand","vers":"0.8.3","yanked":false}
+{"name":"rand","vers":"0.9.0-alpha.1","yanked":false}
+"#;
+        let versions = versions(content);
+        assert_eq!(newest_matching(&versions, "0.7").as_deref(), Some("0.7.3"));
+        assert_eq!(
+            newest_matching(&versions, "0.8.0").as_deref(),
+            Some("0.8.3")
+        );
+        assert_eq!(newest_matching(&versions, "0.9"), None);
+    }
+}
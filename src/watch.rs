@@ -0,0 +1,107 @@
+//! Re-runs the update when manifests change.
+
+use notify::{RecursiveMode, Watcher};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+
+/// How long to wait for the events of a change to settle before re-running.
+const DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// The contents of the watched files, as of the last run.
+pub struct Snapshot {
+    contents: Vec<(PathBuf, Option<String>)>,
+}
+
+impl Snapshot {
+    /// Records the current contents of `paths`.
+    pub fn new(paths: &[PathBuf]) -> Self {
+        let contents = paths
+            .iter()
+            .map(|path| (path.clone(), fs::read_to_string(path).ok()))
+            .collect();
+        Self { contents }
+    }
+
+    /// Returns the files whose content changed since the snapshot was taken.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        self.contents
+            .iter()
+            .filter(|(path, content)| &fs::read_to_string(path).ok() != content)
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
+}
+
+/// Calls `rerun` every time some of the files at `paths` change, until the watcher fails.
+/// Changes are compared to the contents left by the previous run, so the writes of `rerun`
+/// itself don't trigger another run.
+pub fn watch(paths: &[PathBuf], mut rerun: impl FnMut()) -> Result<(), String> {
+    // events report canonical paths
+    let paths: Vec<_> = paths
+        .iter()
+        .map(|path| fs::canonicalize(path).unwrap_or_else(|_| path.clone()))
+        .collect();
+    let paths = paths.as_slice();
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(sender).map_err(|err| err.to_string())?;
+
+    // editors often save by replacing the file, so we watch the directories holding them
+    let mut dirs: Vec<&Path> = paths.iter().filter_map(|path| path.parent()).collect();
+    dirs.sort_unstable();
+    dirs.dedup();
+    for dir in dirs {
+        watcher
+            .watch(dir, RecursiveMode::NonRecursive)
+            .map_err(|err| format!("could not watch {}: {}", dir.display(), err))?;
+    }
+
+    let mut snapshot = Snapshot::new(paths);
+    for event in &receiver {
+        let event = event.map_err(|err| err.to_string())?;
+        if !event.paths.iter().any(|path| paths.contains(path)) {
+            continue;
+        }
+
+        // wait for the burst of events of a save to end
+        while receiver.recv_timeout(DEBOUNCE).is_ok() {}
+
+        let changed = snapshot.changed();
+        if changed.is_empty() {
+            continue;
+        }
+        for path in changed {
+            eprintln!("{} changed, updating again", path.display());
+        }
+        rerun();
+        snapshot = Snapshot::new(paths);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("Cargo.toml");
+        fs::write(&manifest, "[dependencies]\nfoo = \"0.1.1\"\n").unwrap();
+        let paths = vec![manifest.clone()];
+
+        // touching a file without changing it is not a change
+        let snapshot = Snapshot::new(&paths);
+        fs::write(&manifest, "[dependencies]\nfoo = \"0.1.1\"\n").unwrap();
+        assert!(snapshot.changed().is_empty());
+
+        fs::write(&manifest, "[dependencies]\nfoo = \"0.2.0\"\n").unwrap();
+        assert_eq!(snapshot.changed(), paths);
+
+        // the writes of a run are part of the next snapshot
+        let snapshot = Snapshot::new(&paths);
+        assert!(snapshot.changed().is_empty());
+    }
+}